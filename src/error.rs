@@ -0,0 +1,44 @@
+use core::fmt;
+
+/// Reasons `SlabAllocator::try_alloc` could not hand out an object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllocError {
+    /// No free object is left and the page provider has no more pages.
+    PoolExhausted,
+    /// Objects are too large to fit even the biggest slab, or aligned beyond
+    /// the page size, so the cache can never allocate.
+    ObjectTooLarge,
+    /// Fault injection failed the allocation on purpose.
+    InjectedFault,
+}
+
+impl fmt::Display for AllocError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AllocError::PoolExhausted => f.write_str("page provider is out of pages"),
+            AllocError::ObjectTooLarge => f.write_str("object does not fit in any slab"),
+            AllocError::InjectedFault => f.write_str("allocation failed by fault injection"),
+        }
+    }
+}
+
+/// Reasons `SlabAllocator::try_free` refuses a pointer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FreeError {
+    /// The pointer is not inside any page owned by this cache.
+    ForeignPointer,
+    /// The pointer is inside one of our pages but not at the start of an object.
+    Misaligned,
+    /// The object is already free.
+    DoubleFree,
+}
+
+impl fmt::Display for FreeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FreeError::ForeignPointer => f.write_str("pointer does not belong to this cache"),
+            FreeError::Misaligned => f.write_str("pointer is not on an object boundary"),
+            FreeError::DoubleFree => f.write_str("object is already free"),
+        }
+    }
+}
@@ -0,0 +1,57 @@
+/// When a cache with fault injection makes `alloc` fail on purpose, to
+/// exercise out-of-memory handling without exhausting any pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultInjection {
+    /// Fail the nth allocation, then the 2nth, 3nth and so on.
+    EveryNth(usize),
+    /// Let the first n allocations through, then fail every one after.
+    AfterN(usize),
+    /// Fail `percent` percent of allocations, picked by a pseudo-random
+    /// sequence started from `seed`, so runs are reproducible.
+    Random { seed: u64, percent: u8 },
+}
+
+// Decides, allocation by allocation, whether to inject a failure
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct FaultInjector {
+    mode: FaultInjection,
+    // Allocations attempted since injection was set up
+    attempts: usize,
+    // xorshift64* state for the random mode, never zero
+    state: u64,
+}
+
+impl FaultInjector {
+    pub(crate) const fn new(mode: FaultInjection) -> Self {
+        let seed = match mode {
+            FaultInjection::Random { seed, .. } => seed,
+            _ => 0,
+        };
+        Self {
+            mode,
+            attempts: 0,
+            // xorshift gets stuck on zero, so swap it for an arbitrary odd constant
+            state: if seed == 0 {
+                0x9e37_79b9_7f4a_7c15
+            } else {
+                seed
+            },
+        }
+    }
+
+    pub(crate) fn should_fail(&mut self) -> bool {
+        self.attempts += 1;
+        match self.mode {
+            FaultInjection::EveryNth(n) => n != 0 && self.attempts.is_multiple_of(n),
+            FaultInjection::AfterN(n) => self.attempts > n,
+            FaultInjection::Random { percent, .. } => self.next_random() % 100 < percent as u64,
+        }
+    }
+
+    fn next_random(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+}
@@ -0,0 +1,183 @@
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::{self, NonNull};
+
+use crate::size_class::SizeClassAllocator;
+use crate::sync::SpinLock;
+
+/// `GlobalAlloc` implementation that routes each `Layout` to the smallest
+/// size class able to hold it.
+///
+/// The size is rounded up to the alignment first, so the chosen class is
+/// always aligned enough. Layouts larger than the biggest size class fail
+/// with a null pointer. The size classes sit behind one spinlock, so the
+/// allocator can be shared between threads.
+pub struct SlabGlobalAlloc {
+    classes: SpinLock<SizeClassAllocator>,
+}
+
+impl SlabGlobalAlloc {
+    pub const fn new() -> Self {
+        Self {
+            classes: SpinLock::new(SizeClassAllocator::new()),
+        }
+    }
+
+    // Size to request from the size classes: a multiple of the alignment
+    // lands in a class whose natural alignment is at least as strict
+    fn request_size(layout: Layout) -> usize {
+        layout.pad_to_align().size()
+    }
+
+    // Object size of the class that serves the layout
+    fn class_size(&self, layout: Layout) -> Option<usize> {
+        self.classes.lock().class_size(Self::request_size(layout))
+    }
+}
+
+impl Default for SlabGlobalAlloc {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl GlobalAlloc for SlabGlobalAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        match self.classes.lock().alloc(Self::request_size(layout)) {
+            Some((ptr, _)) => ptr.as_ptr(),
+            None => ptr::null_mut(),
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let Some(ptr) = NonNull::new(ptr) else {
+            return;
+        };
+        self.classes.lock().free(ptr, Self::request_size(layout));
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        // SAFETY: The caller guarantees new_size is valid for layout.align().
+        let new_layout = unsafe { Layout::from_size_align_unchecked(new_size, layout.align()) };
+
+        // Same size class, so the object already has room
+        if self.class_size(layout) == self.class_size(new_layout) {
+            return ptr;
+        }
+
+        // SAFETY: new_layout has a non-zero size and a valid alignment.
+        let new_ptr = unsafe { self.alloc(new_layout) };
+        if !new_ptr.is_null() {
+            // SAFETY: Both objects are at least min(old, new) bytes long and distinct.
+            unsafe {
+                ptr::copy_nonoverlapping(ptr, new_ptr, layout.size().min(new_size));
+                self.dealloc(ptr, layout);
+            }
+        }
+        new_ptr
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lock_pool;
+
+    #[test]
+    fn test_routes_by_size_class() {
+        let _pool = lock_pool();
+        let global = SlabGlobalAlloc::new();
+
+        assert_eq!(global.class_size(Layout::new::<u8>()), Some(8));
+        assert_eq!(global.class_size(Layout::new::<[u8; 100]>()), Some(128));
+        assert_eq!(global.class_size(Layout::new::<[u8; 4096]>()), None);
+
+        unsafe {
+            let layout = Layout::new::<[u64; 3]>();
+            let ptr = global.alloc(layout);
+            assert!(!ptr.is_null());
+            assert_eq!(ptr as usize % layout.align(), 0);
+
+            // Freed object is handed out again by the same cache
+            global.dealloc(ptr, layout);
+            assert_eq!(global.alloc(layout), ptr);
+        }
+    }
+
+    #[test]
+    fn test_realloc_preserves_contents() {
+        let _pool = lock_pool();
+        let global = SlabGlobalAlloc::new();
+
+        unsafe {
+            let layout = Layout::array::<u8>(16).unwrap();
+            let ptr = global.alloc(layout);
+            for i in 0..16 {
+                *ptr.add(i) = i as u8;
+            }
+
+            let grown = global.realloc(ptr, layout, 200);
+            assert!(!grown.is_null());
+            assert_ne!(grown, ptr);
+            for i in 0..16 {
+                assert_eq!(*grown.add(i), i as u8);
+            }
+        }
+    }
+
+    #[test]
+    fn test_honours_layout_alignment() {
+        let _pool = lock_pool();
+        let global = SlabGlobalAlloc::new();
+
+        unsafe {
+            for align in [16, 32, 64, 256] {
+                let layout = Layout::from_size_align(24, align).unwrap();
+                let ptr = global.alloc(layout);
+                assert!(!ptr.is_null());
+                assert_eq!(ptr as usize % align, 0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_shared_between_threads() {
+        let _pool = lock_pool();
+        let global = SlabGlobalAlloc::new();
+
+        std::thread::scope(|s| {
+            for id in 0..4u8 {
+                let global = &global;
+                s.spawn(move || unsafe {
+                    let layout = Layout::new::<[u8; 48]>();
+                    for _ in 0..1_000 {
+                        let ptr = global.alloc(layout);
+                        assert!(!ptr.is_null());
+                        ptr.write_bytes(id, 48);
+                        std::thread::yield_now();
+                        assert!((0..48).all(|i| *ptr.add(i) == id));
+                        global.dealloc(ptr, layout);
+                    }
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn test_oversized_layouts_return_null() {
+        let _pool = lock_pool();
+        let global = SlabGlobalAlloc::new();
+
+        unsafe {
+            assert!(
+                global
+                    .alloc(Layout::from_size_align(8192, 8).unwrap())
+                    .is_null()
+            );
+            assert!(
+                global
+                    .alloc(Layout::from_size_align(8, 4096).unwrap())
+                    .is_null()
+            );
+        }
+    }
+}
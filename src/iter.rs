@@ -0,0 +1,178 @@
+use core::ptr::{self, NonNull};
+
+use crate::pool::PageProvider;
+use crate::{Page, SlabAllocator};
+
+/// Which list of its cache a slab is on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageState {
+    /// Some objects handed out, some free.
+    Partial,
+    /// Every object handed out.
+    Full,
+    /// No object handed out, cached for the next refill.
+    Empty,
+}
+
+/// Occupancy of one slab, yielded by `SlabAllocator::pages`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageInfo {
+    /// Start of the slab, where its header lives.
+    pub addr: NonNull<u8>,
+    /// Contiguous pages the slab spans.
+    pub pages: usize,
+    pub state: PageState,
+    /// Objects the slab holds.
+    pub objects: usize,
+    /// Objects currently handed out.
+    pub live: usize,
+}
+
+impl PageInfo {
+    /// Objects of the slab that are free.
+    pub fn free(&self) -> usize {
+        self.objects - self.live
+    }
+}
+
+/// State of one object slot, yielded by `SlabAllocator::objects`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ObjectInfo {
+    pub ptr: NonNull<u8>,
+    /// Whether the object is currently handed out.
+    pub allocated: bool,
+}
+
+// Walks the partial, full and empty lists of a cache in turn
+struct PageCursor<'a, P: PageProvider> {
+    slab: &'a SlabAllocator<P>,
+    lists: [(*mut Page, PageState); 3],
+    next_list: usize,
+    page: *mut Page,
+}
+
+impl<'a, P: PageProvider> PageCursor<'a, P> {
+    fn new(slab: &'a SlabAllocator<P>) -> Self {
+        Self {
+            slab,
+            lists: [
+                (slab.partial, PageState::Partial),
+                (slab.full, PageState::Full),
+                (slab.empty, PageState::Empty),
+            ],
+            next_list: 0,
+            page: ptr::null_mut(),
+        }
+    }
+
+    fn next_page(&mut self) -> Option<(*mut Page, PageState)> {
+        loop {
+            if !self.page.is_null() {
+                let page = self.page;
+                // SAFETY: The borrow keeps the cache, and so its lists,
+                // unchanged, and every page on them is one of its slabs.
+                self.page = unsafe { (*page).next };
+                return Some((page, self.lists[self.next_list - 1].1));
+            }
+            self.page = self.lists.get(self.next_list)?.0;
+            self.next_list += 1;
+        }
+    }
+}
+
+/// Iterator over the slabs of a cache, returned by `SlabAllocator::pages`.
+pub struct Pages<'a, P: PageProvider> {
+    cursor: PageCursor<'a, P>,
+}
+
+impl<'a, P: PageProvider> Pages<'a, P> {
+    pub(crate) fn new(slab: &'a SlabAllocator<P>) -> Self {
+        Self {
+            cursor: PageCursor::new(slab),
+        }
+    }
+}
+
+impl<P: PageProvider> Iterator for Pages<'_, P> {
+    type Item = PageInfo;
+
+    fn next(&mut self) -> Option<PageInfo> {
+        let (page, state) = self.cursor.next_page()?;
+        let slab = self.cursor.slab;
+        Some(PageInfo {
+            // SAFETY: Pages on our lists are never null.
+            addr: unsafe { NonNull::new_unchecked(page as *mut u8) },
+            pages: slab.slab_pages,
+            state,
+            objects: slab.objects_per_slab,
+            // SAFETY: The page is one of the cache's slabs.
+            live: unsafe { (*page).live },
+        })
+    }
+}
+
+/// Iterator over every object slot of a cache, slab by slab, returned by
+/// `SlabAllocator::objects`.
+pub struct Objects<'a, P: PageProvider> {
+    cursor: PageCursor<'a, P>,
+    page: *mut Page,
+    index: usize,
+}
+
+impl<'a, P: PageProvider> Objects<'a, P> {
+    pub(crate) fn new(slab: &'a SlabAllocator<P>) -> Self {
+        Self {
+            cursor: PageCursor::new(slab),
+            page: ptr::null_mut(),
+            index: 0,
+        }
+    }
+}
+
+impl<P: PageProvider> Iterator for Objects<'_, P> {
+    type Item = ObjectInfo;
+
+    fn next(&mut self) -> Option<ObjectInfo> {
+        let slab = self.cursor.slab;
+        if self.page.is_null() || self.index == slab.objects_per_slab {
+            self.page = self.cursor.next_page()?.0;
+            self.index = 0;
+        }
+
+        let index = self.index;
+        self.index += 1;
+        // SAFETY: The page is one of the cache's slabs and index is in range.
+        unsafe {
+            let obj = slab.data_start(self.page).add(index * slab.object_size);
+            Some(ObjectInfo {
+                ptr: NonNull::new_unchecked(obj),
+                allocated: slab.slot_allocated(self.page, index),
+            })
+        }
+    }
+}
+
+/// Iterator over the objects a cache has handed out, returned by
+/// `SlabAllocator::live_objects`.
+pub struct LiveObjects<'a, P: PageProvider> {
+    objects: Objects<'a, P>,
+}
+
+impl<'a, P: PageProvider> LiveObjects<'a, P> {
+    pub(crate) fn new(slab: &'a SlabAllocator<P>) -> Self {
+        Self {
+            objects: Objects::new(slab),
+        }
+    }
+}
+
+impl<P: PageProvider> Iterator for LiveObjects<'_, P> {
+    type Item = NonNull<u8>;
+
+    fn next(&mut self) -> Option<NonNull<u8>> {
+        self.objects
+            .by_ref()
+            .find(|object| object.allocated)
+            .map(|object| object.ptr)
+    }
+}
@@ -0,0 +1,149 @@
+use core::alloc::Layout;
+use core::ptr::NonNull;
+
+use crate::SlabAllocator;
+use crate::error::{AllocError, FreeError};
+use crate::pool::{PageProvider, StaticPool};
+use crate::sync::{SpinLock, SpinLockGuard};
+
+/// Slab cache behind a spinlock, so it can be shared between threads.
+pub struct LockedSlab<P: PageProvider = StaticPool> {
+    slab: SpinLock<SlabAllocator<P>>,
+}
+
+impl LockedSlab {
+    pub const fn new(object_size: usize) -> Self {
+        Self::from_slab(SlabAllocator::new(object_size))
+    }
+
+    /// Create a cache whose objects all satisfy `layout`'s size and alignment.
+    pub const fn with_layout(layout: Layout) -> Self {
+        Self::from_slab(SlabAllocator::with_layout(layout))
+    }
+}
+
+impl<P: PageProvider> LockedSlab<P> {
+    /// Wrap a configured cache, for example one built with `with_provider`.
+    pub const fn from_slab(slab: SlabAllocator<P>) -> Self {
+        Self {
+            slab: SpinLock::new(slab),
+        }
+    }
+
+    pub fn alloc(&self) -> Option<NonNull<u8>> {
+        self.slab.lock().alloc()
+    }
+
+    /// Allocate an object, reporting why when none can be handed out.
+    pub fn try_alloc(&self) -> Result<NonNull<u8>, AllocError> {
+        self.slab.lock().try_alloc()
+    }
+
+    pub fn free(&self, ptr: NonNull<u8>) {
+        self.slab.lock().free(ptr);
+    }
+
+    /// Free an object, reporting pointers the cache can't take back.
+    pub fn try_free(&self, ptr: NonNull<u8>) -> Result<(), FreeError> {
+        self.slab.lock().try_free(ptr)
+    }
+
+    /// Lock the cache for several operations in a row.
+    pub fn lock(&self) -> SpinLockGuard<'_, SlabAllocator<P>> {
+        self.slab.lock()
+    }
+
+    pub fn into_inner(self) -> SlabAllocator<P> {
+        self.slab.into_inner()
+    }
+}
+
+impl Default for LockedSlab {
+    fn default() -> Self {
+        Self::new(core::mem::size_of::<usize>())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MAX_PAGES;
+    use crate::{lock_pool, test_region};
+    use std::thread;
+    use std::vec::Vec;
+
+    const THREADS: usize = 4;
+    const ROUNDS: usize = 2_000;
+
+    // Allocate a batch, stamp every object with a tag, and check nobody else
+    // was handed the same object before freeing it again
+    fn hammer(cache: &LockedSlab<impl PageProvider>, id: usize, batch: usize) {
+        let mut held = Vec::with_capacity(batch);
+        for round in 0..ROUNDS {
+            let tag = (id << 32) | round;
+            for _ in 0..batch {
+                let ptr = cache.alloc().unwrap().cast::<usize>();
+                unsafe { ptr.as_ptr().write(tag) };
+                held.push(ptr);
+            }
+            thread::yield_now();
+            for ptr in held.drain(..) {
+                assert_eq!(unsafe { ptr.as_ptr().read() }, tag);
+                cache.try_free(ptr.cast()).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_threads_never_share_an_object() {
+        let _pool = lock_pool();
+        let cache = LockedSlab::new(64);
+
+        thread::scope(|s| {
+            for id in 0..THREADS {
+                let cache = &cache;
+                s.spawn(move || hammer(cache, id, 16));
+            }
+        });
+
+        // Every object came back, so the cache holds nothing but empty slabs
+        let mut slab = cache.into_inner();
+        assert!(slab.alloc().is_some());
+    }
+
+    #[test]
+    fn test_region_pages_return_after_threads_finish() {
+        let slab = SlabAllocator::new(128).in_region(test_region(8));
+        let per_slab = slab.objects_per_slab;
+        let cache = LockedSlab::from_slab(slab);
+
+        // Batches span several pages, so slabs are taken and released concurrently
+        thread::scope(|s| {
+            for id in 0..THREADS {
+                let cache = &cache;
+                s.spawn(move || hammer(cache, id, 40));
+            }
+        });
+
+        let mut slab = cache.lock();
+        let objects: Vec<_> = core::iter::from_fn(|| slab.alloc()).collect();
+        assert_eq!(objects.len(), 8 * per_slab);
+    }
+
+    #[test]
+    fn test_caches_on_separate_threads_share_static_pool() {
+        let _pool = lock_pool();
+
+        // One cache per thread, so only the shared pool is contended. Batches
+        // need two pages, and each cache gives its empty page back when dropped.
+        thread::scope(|s| {
+            for id in 0..THREADS {
+                s.spawn(move || hammer(&LockedSlab::new(64), id, 100));
+            }
+        });
+
+        let mut pool = StaticPool;
+        let left = core::iter::from_fn(|| pool.acquire_page()).count();
+        assert_eq!(left, MAX_PAGES);
+    }
+}
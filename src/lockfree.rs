@@ -0,0 +1,281 @@
+use core::alloc::Layout;
+use core::cell::UnsafeCell;
+use core::mem::ManuallyDrop;
+use core::ptr::{self, NonNull};
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+use crate::pool::{PageProvider, StaticPool};
+use crate::sync::SpinLock;
+use crate::sys::compare_exchange_pair;
+use crate::{FreeObject, SlabAllocator};
+
+// Free list head paired with a counter bumped by every push and pop, swapped
+// as one double-width value. A thread that read the head before another one
+// popped and pushed the same object back sees a different tag, so its stale
+// compare-and-swap fails instead of installing a `next` that is out of date.
+#[repr(C, align(16))]
+struct TaggedHead(UnsafeCell<[usize; 2]>);
+
+impl TaggedHead {
+    const fn new() -> Self {
+        Self(UnsafeCell::new([0, 0]))
+    }
+
+    // Atomic snapshot of [head, tag]
+    fn load(&self) -> [usize; 2] {
+        // SAFETY: The cell is aligned, and swapping zero for zero never changes it.
+        match unsafe { compare_exchange_pair(self.0.get(), [0, 0], [0, 0]) } {
+            Ok(found) | Err(found) => found,
+        }
+    }
+
+    fn compare_exchange(&self, current: [usize; 2], new: [usize; 2]) -> Result<(), [usize; 2]> {
+        // SAFETY: The cell is aligned and only ever accessed atomically.
+        unsafe { compare_exchange_pair(self.0.get(), current, new) }.map(|_| ())
+    }
+}
+
+/// Slab cache whose `alloc` and `free` never take a lock, for interrupt
+/// handlers and other code that must not spin.
+///
+/// Free objects sit on a lock-free list with an ABA-safe tagged head. The
+/// list never grows on its own: `reserve` moves objects over from a regular
+/// `SlabAllocator`, and that part does take a spinlock, so call it outside of
+/// interrupt context. Objects only go back to the slab in `into_inner` or
+/// when the cache is dropped, so pages stay mapped while other threads may
+/// still read a stale `next` link.
+pub struct LockFreeSlab<P: PageProvider = StaticPool> {
+    head: TaggedHead,
+    link_offset: usize,
+    slab: SpinLock<SlabAllocator<P>>,
+}
+
+// SAFETY: The head is only touched atomically, the slab is behind its lock,
+// and objects on the list belong to the cache.
+unsafe impl<P: PageProvider + Send> Sync for LockFreeSlab<P> {}
+unsafe impl<P: PageProvider + Send> Send for LockFreeSlab<P> {}
+
+impl LockFreeSlab {
+    pub const fn new(object_size: usize) -> Self {
+        Self::from_slab(SlabAllocator::new(object_size))
+    }
+
+    /// Create a cache whose objects all satisfy `layout`'s size and alignment.
+    pub const fn with_layout(layout: Layout) -> Self {
+        Self::from_slab(SlabAllocator::with_layout(layout))
+    }
+}
+
+impl<P: PageProvider> LockFreeSlab<P> {
+    /// Serve objects of a configured cache without locks. The list starts
+    /// empty, so `reserve` some before the first `alloc`.
+    ///
+    /// Panics if the cache tracks free objects with a bitmap: its objects
+    /// have no room reserved for the link this list threads through them.
+    pub const fn from_slab(slab: SlabAllocator<P>) -> Self {
+        assert!(
+            !slab.track_bitmap,
+            "bitmap-tracked objects can't hold a free list link"
+        );
+        Self {
+            head: TaggedHead::new(),
+            link_offset: slab.link_offset,
+            slab: SpinLock::new(slab),
+        }
+    }
+
+    /// Move up to `count` objects from the slab onto the lock-free list,
+    /// returning how many were added. Takes the slab's spinlock.
+    pub fn reserve(&self, count: usize) -> usize {
+        let mut slab = self.slab.lock();
+        for added in 0..count {
+            match slab.alloc() {
+                Some(ptr) => self.free(ptr),
+                None => return added,
+            }
+        }
+        count
+    }
+
+    /// Pop an object off the free list, or `None` once the reserve is used up.
+    pub fn alloc(&self) -> Option<NonNull<u8>> {
+        let mut head = self.head.load();
+        loop {
+            let node = NonNull::new(head[0] as *mut FreeObject)?;
+            // The node may have been popped in the meantime and its link
+            // overwritten, but its page stays mapped and the tag check below
+            // throws the stale value away.
+            let next = self.link(node).load(Ordering::Relaxed);
+            match self
+                .head
+                .compare_exchange(head, [next as usize, head[1].wrapping_add(1)])
+            {
+                // SAFETY: Links point link_offset bytes into their object.
+                Ok(()) => return Some(unsafe { node.cast::<u8>().sub(self.link_offset) }),
+                Err(found) => head = found,
+            }
+        }
+    }
+
+    /// Push an object back on the free list.
+    ///
+    /// `ptr` must come from `alloc` on this cache and not be used afterwards.
+    /// Unlike `SlabAllocator::try_free`, nothing is checked.
+    pub fn free(&self, ptr: NonNull<u8>) {
+        // SAFETY: The link slot lies inside the object's stride.
+        let node = unsafe { ptr.add(self.link_offset) }.cast::<FreeObject>();
+        let mut head = self.head.load();
+        loop {
+            self.link(node)
+                .store(head[0] as *mut FreeObject, Ordering::Relaxed);
+            match self
+                .head
+                .compare_exchange(head, [node.as_ptr() as usize, head[1].wrapping_add(1)])
+            {
+                Ok(()) => return,
+                Err(found) => head = found,
+            }
+        }
+    }
+
+    /// Give every object on the list back to the slab and return it.
+    pub fn into_inner(self) -> SlabAllocator<P> {
+        let mut this = ManuallyDrop::new(self);
+        this.drain();
+        // SAFETY: this is never dropped, so the slab is moved out only once.
+        unsafe { ptr::read(&this.slab) }.into_inner()
+    }
+
+    // Give every object on the list back to the slab
+    fn drain(&mut self) {
+        let mut slab = self.slab.lock();
+        let mut node = self.head.load()[0] as *mut FreeObject;
+        while let Some(free) = NonNull::new(node) {
+            // SAFETY: With the cache borrowed mutably, the list is ours alone.
+            unsafe {
+                node = (*free.as_ptr()).next;
+                slab.free(free.cast::<u8>().sub(self.link_offset));
+            }
+        }
+        drop(slab);
+        self.head = TaggedHead::new();
+    }
+
+    // The link of a free object, read and written atomically since other
+    // threads may look at it concurrently
+    fn link(&self, node: NonNull<FreeObject>) -> &AtomicPtr<FreeObject> {
+        // SAFETY: The link is an aligned pointer-sized slot in a mapped page.
+        unsafe { AtomicPtr::from_ptr(ptr::addr_of_mut!((*node.as_ptr()).next)) }
+    }
+}
+
+impl<P: PageProvider> Drop for LockFreeSlab<P> {
+    fn drop(&mut self) {
+        self.drain();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lock_pool, test_region};
+    use std::thread;
+    use std::vec::Vec;
+
+    #[test]
+    fn test_tag_defeats_aba() {
+        let head = TaggedHead::new();
+        head.compare_exchange([0, 0], [0x1000, 1]).unwrap();
+        let stale = head.load();
+
+        // Pop 0x1000, pop 0x2000, push 0x1000: same pointer, newer tag
+        head.compare_exchange(stale, [0x2000, 2]).unwrap();
+        head.compare_exchange([0x2000, 2], [0, 3]).unwrap();
+        head.compare_exchange([0, 3], [0x1000, 4]).unwrap();
+
+        assert_eq!(head.compare_exchange(stale, [0x3000, 2]), Err([0x1000, 4]));
+    }
+
+    #[test]
+    fn test_alloc_only_serves_reserved_objects() {
+        let _pool = lock_pool();
+        let cache = LockFreeSlab::new(32);
+        assert!(cache.alloc().is_none());
+
+        assert_eq!(cache.reserve(3), 3);
+        let objects: Vec<_> = core::iter::from_fn(|| cache.alloc()).collect();
+        assert_eq!(objects.len(), 3);
+
+        for &ptr in &objects {
+            cache.free(ptr);
+        }
+        assert_eq!(cache.alloc(), Some(objects[2]));
+    }
+
+    #[test]
+    fn test_ctor_state_survives_the_lock_free_list() {
+        let _pool = lock_pool();
+        let cache = LockFreeSlab::from_slab(
+            SlabAllocator::new(8).with_ctor(|obj| unsafe { obj.cast::<u64>().write(0xabcd) }),
+        );
+        cache.reserve(4);
+
+        let objects: Vec<_> = core::iter::from_fn(|| cache.alloc()).collect();
+        for &ptr in &objects {
+            cache.free(ptr);
+        }
+        while let Some(ptr) = cache.alloc() {
+            assert_eq!(unsafe { ptr.cast::<u64>().read() }, 0xabcd);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "free list link")]
+    fn test_bitmap_tracked_slab_is_rejected() {
+        // Two-byte objects packed back to back have nowhere to put a link
+        let slab = SlabAllocator::with_layout(Layout::new::<u16>()).track_with_bitmap();
+        LockFreeSlab::from_slab(slab);
+    }
+
+    #[test]
+    fn test_into_inner_returns_objects_to_the_slab() {
+        let slab = SlabAllocator::new(64).in_region(test_region(2));
+        let per_slab = slab.objects_per_slab;
+        let cache = LockFreeSlab::from_slab(slab);
+        assert_eq!(cache.reserve(usize::MAX), 2 * per_slab);
+
+        let mut slab = cache.into_inner();
+        assert_eq!(core::iter::from_fn(|| slab.alloc()).count(), 2 * per_slab);
+    }
+
+    #[test]
+    fn test_threads_never_share_an_object() {
+        let _pool = lock_pool();
+        let cache = LockFreeSlab::new(64);
+        cache.reserve(100);
+
+        thread::scope(|s| {
+            for id in 0..4usize {
+                let cache = &cache;
+                s.spawn(move || {
+                    let mut held = Vec::new();
+                    for round in 0..5_000 {
+                        let tag = (id << 32) | round;
+                        for _ in 0..8 {
+                            let ptr = cache.alloc().unwrap().cast::<usize>();
+                            unsafe { ptr.as_ptr().write(tag) };
+                            held.push(ptr);
+                        }
+                        for ptr in held.drain(..) {
+                            assert_eq!(unsafe { ptr.as_ptr().read() }, tag);
+                            cache.free(ptr.cast());
+                        }
+                    }
+                });
+            }
+        });
+
+        let mut slab = cache.into_inner();
+        assert!(slab.alloc().is_some());
+    }
+}
@@ -0,0 +1,324 @@
+use core::alloc::Layout;
+use core::mem;
+use core::ptr::{self, NonNull};
+
+use crate::SlabAllocator;
+use crate::pool::{PageProvider, StaticPool};
+use crate::sync::SpinLock;
+
+// Objects a magazine holds
+const MAGAZINE_SIZE: usize = 16;
+
+// Full magazines the depot keeps before objects go back to the slab
+const DEPOT_MAGAZINES: usize = 8;
+
+// Stack of free objects, exchanged whole between caches and the depot
+struct Magazine {
+    rounds: usize,
+    objects: [*mut u8; MAGAZINE_SIZE],
+}
+
+impl Magazine {
+    const fn new() -> Self {
+        Self {
+            rounds: 0,
+            objects: [ptr::null_mut(); MAGAZINE_SIZE],
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.rounds == 0
+    }
+
+    fn is_full(&self) -> bool {
+        self.rounds == MAGAZINE_SIZE
+    }
+
+    fn pop(&mut self) -> Option<NonNull<u8>> {
+        if self.is_empty() {
+            return None;
+        }
+        self.rounds -= 1;
+        NonNull::new(self.objects[self.rounds])
+    }
+
+    fn push(&mut self, ptr: NonNull<u8>) {
+        debug_assert!(!self.is_full());
+        self.objects[self.rounds] = ptr.as_ptr();
+        self.rounds += 1;
+    }
+}
+
+struct DepotInner<P: PageProvider> {
+    slab: SlabAllocator<P>,
+    full: [Magazine; DEPOT_MAGAZINES],
+    full_count: usize,
+}
+
+// SAFETY: The depot owns the objects in its magazines, like the slab owns its pages.
+unsafe impl<P: PageProvider + Send> Send for DepotInner<P> {}
+
+impl<P: PageProvider> DepotInner<P> {
+    // Swap a full magazine from the depot into `magazine`, which must be empty
+    fn take_full(&mut self, magazine: &mut Magazine) -> bool {
+        if self.full_count == 0 {
+            return false;
+        }
+        self.full_count -= 1;
+        mem::swap(&mut self.full[self.full_count], magazine);
+        true
+    }
+
+    // Swap the full `magazine` into the depot, leaving it empty
+    fn put_full(&mut self, magazine: &mut Magazine) -> bool {
+        if self.full_count == DEPOT_MAGAZINES {
+            return false;
+        }
+        mem::swap(&mut self.full[self.full_count], magazine);
+        self.full_count += 1;
+        magazine.rounds = 0;
+        true
+    }
+
+    // Fill `magazine` straight from the slab
+    fn fill(&mut self, magazine: &mut Magazine) {
+        while !magazine.is_full() {
+            match self.slab.alloc() {
+                Some(ptr) => magazine.push(ptr),
+                None => break,
+            }
+        }
+    }
+
+    // Give every object in `magazine` back to the slab
+    fn drain(&mut self, magazine: &mut Magazine) {
+        while let Some(ptr) = magazine.pop() {
+            self.slab.free(ptr);
+        }
+    }
+}
+
+/// Shared half of the magazine layer: a slab cache plus a depot of full
+/// magazines, behind one spinlock.
+///
+/// Each thread or core allocates through its own `MagazineCache`, which only
+/// takes the lock to swap a whole magazine of objects with the depot, or to
+/// refill or drain one against the slab when the depot has none to spare.
+pub struct Depot<P: PageProvider = StaticPool> {
+    inner: SpinLock<DepotInner<P>>,
+}
+
+impl Depot {
+    pub const fn new(object_size: usize) -> Self {
+        Self::from_slab(SlabAllocator::new(object_size))
+    }
+
+    /// Create a depot whose objects all satisfy `layout`'s size and alignment.
+    pub const fn with_layout(layout: Layout) -> Self {
+        Self::from_slab(SlabAllocator::with_layout(layout))
+    }
+}
+
+impl<P: PageProvider> Depot<P> {
+    /// Put a configured cache behind the depot.
+    pub const fn from_slab(slab: SlabAllocator<P>) -> Self {
+        Self {
+            inner: SpinLock::new(DepotInner {
+                slab,
+                full: [const { Magazine::new() }; DEPOT_MAGAZINES],
+                full_count: 0,
+            }),
+        }
+    }
+
+    /// Create a cache for one thread or core, starting with empty magazines.
+    pub fn cache(&self) -> MagazineCache<'_, P> {
+        MagazineCache {
+            depot: self,
+            loaded: Magazine::new(),
+            previous: Magazine::new(),
+        }
+    }
+
+    /// Give the objects in the depot's magazines back to the slab, so empty
+    /// slabs can be released.
+    pub fn flush(&self) {
+        let mut inner = self.inner.lock();
+        let mut magazine = Magazine::new();
+        while inner.take_full(&mut magazine) {
+            inner.drain(&mut magazine);
+        }
+    }
+}
+
+impl<P: PageProvider> Drop for Depot<P> {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// Per-thread (or per-core) front end of a `Depot`.
+///
+/// Holds a loaded and a previous magazine, so runs of allocations or frees
+/// are served without the lock. Objects freed here are only checked by the
+/// slab once their magazine is drained, so `free` does not report errors.
+pub struct MagazineCache<'a, P: PageProvider = StaticPool> {
+    depot: &'a Depot<P>,
+    loaded: Magazine,
+    previous: Magazine,
+}
+
+// SAFETY: The cache owns the objects in its magazines, and the depot is Sync.
+unsafe impl<P: PageProvider + Send> Send for MagazineCache<'_, P> {}
+
+impl<P: PageProvider> MagazineCache<'_, P> {
+    pub fn alloc(&mut self) -> Option<NonNull<u8>> {
+        if let Some(ptr) = self.loaded.pop() {
+            return Some(ptr);
+        }
+        if !self.previous.is_empty() {
+            mem::swap(&mut self.loaded, &mut self.previous);
+            return self.loaded.pop();
+        }
+
+        // Both magazines are empty: swap one for a full magazine from the
+        // depot, or fill it from the slab
+        let mut inner = self.depot.inner.lock();
+        if !inner.take_full(&mut self.loaded) {
+            inner.fill(&mut self.loaded);
+        }
+        drop(inner);
+        self.loaded.pop()
+    }
+
+    pub fn free(&mut self, ptr: NonNull<u8>) {
+        if !self.loaded.is_full() {
+            return self.loaded.push(ptr);
+        }
+        if !self.previous.is_full() {
+            mem::swap(&mut self.loaded, &mut self.previous);
+            return self.loaded.push(ptr);
+        }
+
+        // Both magazines are full: hand one to the depot, or drain it into
+        // the slab when the depot has no room
+        let mut inner = self.depot.inner.lock();
+        if !inner.put_full(&mut self.loaded) {
+            inner.drain(&mut self.loaded);
+        }
+        drop(inner);
+        self.loaded.push(ptr);
+    }
+
+    /// Give every object this cache holds back to the depot.
+    pub fn flush(&mut self) {
+        let mut inner = self.depot.inner.lock();
+        for magazine in [&mut self.loaded, &mut self.previous] {
+            if !(magazine.is_full() && inner.put_full(magazine)) {
+                inner.drain(magazine);
+            }
+        }
+    }
+}
+
+impl<P: PageProvider> Drop for MagazineCache<'_, P> {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lock_pool, test_region};
+    use std::thread;
+    use std::vec::Vec;
+
+    #[test]
+    fn test_freed_objects_are_reused_lifo() {
+        let _pool = lock_pool();
+        let depot = Depot::new(32);
+        let mut cache = depot.cache();
+
+        let a = cache.alloc().unwrap();
+        let b = cache.alloc().unwrap();
+        assert_ne!(a, b);
+        cache.free(a);
+        cache.free(b);
+        assert_eq!(cache.alloc(), Some(b));
+        assert_eq!(cache.alloc(), Some(a));
+    }
+
+    #[test]
+    fn test_full_magazines_move_between_caches() {
+        let _pool = lock_pool();
+        let depot = Depot::new(32);
+        let mut first = depot.cache();
+        let mut second = depot.cache();
+
+        // Three magazines worth: two stay in the cache, one goes to the depot
+        let objects: Vec<_> = (0..3 * MAGAZINE_SIZE)
+            .map(|_| first.alloc().unwrap())
+            .collect();
+        for &ptr in &objects {
+            first.free(ptr);
+        }
+        assert_eq!(depot.inner.lock().full_count, 1);
+
+        // The other cache picks the depot's magazine up whole
+        let taken = second.alloc().unwrap();
+        assert!(objects[MAGAZINE_SIZE..2 * MAGAZINE_SIZE].contains(&taken));
+        assert_eq!(depot.inner.lock().full_count, 0);
+    }
+
+    #[test]
+    fn test_flush_returns_every_object_to_the_slab() {
+        let slab = SlabAllocator::new(64).in_region(test_region(4));
+        let per_slab = slab.objects_per_slab;
+        let depot = Depot::from_slab(slab);
+
+        {
+            let mut cache = depot.cache();
+            let objects: Vec<_> = core::iter::from_fn(|| cache.alloc()).collect();
+            for ptr in objects {
+                cache.free(ptr);
+            }
+        }
+        depot.flush();
+
+        // Nothing is held by magazines any more, so the slab hands out everything
+        let mut inner = depot.inner.lock();
+        let objects = core::iter::from_fn(|| inner.slab.alloc()).count();
+        assert_eq!(objects, 4 * per_slab);
+    }
+
+    #[test]
+    fn test_threads_exchange_magazines_safely() {
+        let _pool = lock_pool();
+        let depot = Depot::new(64);
+
+        thread::scope(|s| {
+            for id in 0..4usize {
+                let depot = &depot;
+                s.spawn(move || {
+                    let mut cache = depot.cache();
+                    let mut held = Vec::new();
+                    for round in 0..2_000 {
+                        let tag = (id << 32) | round;
+                        // Batches larger than both magazines go through the depot
+                        for _ in 0..40 {
+                            let ptr = cache.alloc().unwrap().cast::<usize>();
+                            unsafe { ptr.as_ptr().write(tag) };
+                            held.push(ptr);
+                        }
+                        thread::yield_now();
+                        for ptr in held.drain(..) {
+                            assert_eq!(unsafe { ptr.as_ptr().read() }, tag);
+                            cache.free(ptr.cast());
+                        }
+                    }
+                });
+            }
+        });
+    }
+}
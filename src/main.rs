@@ -1,7 +1,21 @@
 #![no_std]
-#![no_main]
+#![cfg_attr(not(test), no_main)]
 
+pub mod error;
+pub mod fault;
+pub mod global;
+pub mod iter;
+pub mod locked;
+pub mod lockfree;
+pub mod magazine;
+pub mod pool;
+pub mod size_class;
+pub mod stats;
+pub mod sync;
 pub mod sys;
+pub mod typed;
+
+extern crate alloc;
 
 #[cfg(test)]
 extern crate std;
@@ -9,159 +23,1057 @@ extern crate std;
 #[cfg(not(test))]
 use core::panic::PanicInfo;
 
+use crate::error::{AllocError, FreeError};
+use crate::fault::{FaultInjection, FaultInjector};
+#[cfg(not(test))]
+use crate::global::SlabGlobalAlloc;
+use crate::iter::{LiveObjects, Objects, Pages};
+#[cfg(test)]
+use crate::pool::MmapPool;
+use crate::pool::{PagePool, PageProvider, StaticPool};
+use crate::stats::SlabStats;
+#[cfg(not(test))]
 use crate::sys::exit;
+#[cfg(not(test))]
+use alloc::{boxed::Box, vec::Vec};
+use core::alloc::Layout;
+use core::mem::MaybeUninit;
 use core::ptr;
 use core::ptr::NonNull;
-use core::ptr::addr_of_mut;
 
 const PAGE_SIZE: usize = 4096;
 
 // How many pages we can allocate
 const MAX_PAGES: usize = 16;
 
-// Memory pool for pages
-static mut PAGE_POOL: [u8; MAX_PAGES * PAGE_SIZE] = [0; MAX_PAGES * PAGE_SIZE];
-static mut PAGE_POOL_USED: usize = 0;
+// Largest slab is 2^MAX_ORDER contiguous pages
+const MAX_ORDER: usize = 3;
+
+// Empty pages a cache keeps for reuse before returning them to the pool
+const MAX_EMPTY_PAGES: usize = 1;
+
+// Route Box, Vec and friends through the slab caches
+#[cfg(not(test))]
+#[global_allocator]
+static GLOBAL: SlabGlobalAlloc = SlabGlobalAlloc::new();
 
-// Slab allocator struct.
-pub struct SlabAllocator {
+// Slab allocator struct, taking its pages from `P`.
+pub struct SlabAllocator<P: PageProvider = StaticPool> {
+    // Bytes the caller asked for
+    size: usize,
+    // Stride between objects, covering the free list link if it sits after the object
     object_size: usize,
-    objects_per_page: usize,
-    free_list: *mut FreeObject,
-    pages: *mut Page,
+    // Alignment objects get, at least layout_align
+    align: usize,
+    // Alignment the caller asked for
+    layout_align: usize,
+    // Offset of the free list link inside a free object
+    link_offset: usize,
+    // Run on every object when a slab is populated, and before one is released
+    ctor: Option<fn(NonNull<u8>)>,
+    dtor: Option<fn(NonNull<u8>)>,
+    // Offset of the first object from the start of its slab
+    data_offset: usize,
+    objects_per_slab: usize,
+    page_size: usize,
+    // Contiguous pages per slab, 2^order
+    slab_pages: usize,
+    check_double_free: bool,
+    // Track free objects with the page bitmap instead of free lists
+    track_bitmap: bool,
+    // Hand out every object zeroed, as if from alloc_zeroed
+    zero_objects: bool,
+    // Granularity of slab coloring, 0 when every slab starts its data at data_offset
+    color_step: usize,
+    // Color offset the next new slab gets
+    next_color: usize,
+    // Makes alloc fail on purpose, for testing out-of-memory paths
+    fault: Option<FaultInjector>,
+    // Words of allocation bitmap after each page header, 0 when double frees aren't checked
+    bitmap_words: usize,
+    // Pages with some objects free, allocated from first
+    partial: *mut Page,
+    // Pages with every object handed out
+    full: *mut Page,
+    // Pages with no live objects, kept for the next refill
+    empty: *mut Page,
+    empty_pages: usize,
+    // Slabs currently owned, on any list
+    slabs: usize,
+    // Counters reported by stats()
+    allocated: usize,
+    freed: usize,
+    failed: usize,
+    high_water: usize,
+    provider: P,
 }
 
+// SAFETY: The cache owns its slabs outright, and the shared pool they come
+// from is synchronized, so it can move to another thread with its provider.
+unsafe impl<P: PageProvider + Send> Send for SlabAllocator<P> {}
+
 /// Free list node stored inside free objects
 struct FreeObject {
     next: *mut FreeObject,
 }
 
-// Page header with a pointer to the next page
+// Page header linking the page into one of its cache's lists
 #[repr(C)]
 struct PageHeader {
     next: *mut PageHeader,
+    prev: *mut PageHeader,
+    // Free objects of this page only
+    free_list: *mut FreeObject,
+    // Objects of this page currently handed out
+    live: usize,
+    // Bytes the first object is shifted past data_offset, for coloring
+    color: usize,
+    // Objects from this index on were never handed out and still hold the
+    // provider's zeroes, apart from their free list link. objects_per_slab
+    // when the page wasn't zeroed or a ctor wrote to them.
+    pristine_from: usize,
 }
 
 // A page is a header followed by the actual data
 type Page = PageHeader;
 
 impl SlabAllocator {
-    pub fn new(object_size: usize) -> Self {
+    pub const fn new(object_size: usize) -> Self {
+        // Natural alignment of the size, capped at a pointer's like the free list needs
+        let pointer_align = core::mem::align_of::<FreeObject>();
+        let natural = if object_size == 0 {
+            pointer_align
+        } else {
+            1 << object_size.trailing_zeros()
+        };
+        let align = if natural < pointer_align {
+            natural
+        } else {
+            pointer_align
+        };
+        Self::from_size_align(object_size, align, StaticPool, PAGE_SIZE)
+    }
+
+    /// Create a cache whose objects all satisfy `layout`'s size and alignment.
+    /// Slabs are only page-aligned, so a cache aligned beyond the page size
+    /// never allocates.
+    pub const fn with_layout(layout: Layout) -> Self {
+        Self::from_size_align(layout.size(), layout.align(), StaticPool, PAGE_SIZE)
+    }
+
+    // Forgets every page of the shared pool, so no cache may still hold one.
+    pub fn reset_pool() {
+        PagePool::shared().reset();
+    }
+}
+
+impl<P: PageProvider> SlabAllocator<P> {
+    const fn from_size_align(size: usize, align: usize, provider: P, page_size: usize) -> Self {
+        let mut slab = Self {
+            size,
+            object_size: 0,
+            align,
+            layout_align: align,
+            link_offset: 0,
+            ctor: None,
+            dtor: None,
+            data_offset: 0,
+            objects_per_slab: 0,
+            page_size,
+            slab_pages: 1,
+            check_double_free: false,
+            track_bitmap: false,
+            zero_objects: false,
+            color_step: 0,
+            next_color: 0,
+            fault: None,
+            bitmap_words: 0,
+            partial: core::ptr::null_mut(),
+            full: core::ptr::null_mut(),
+            empty: core::ptr::null_mut(),
+            empty_pages: 0,
+            slabs: 0,
+            allocated: 0,
+            freed: 0,
+            failed: 0,
+            high_water: 0,
+            provider,
+        };
+        slab.compute_stride();
+        slab.compute_page_layout();
+        slab
+    }
+
+    /// Run `ctor` on every object when a slab is populated, so `alloc` hands out
+    /// already-constructed objects. Objects must be freed in constructed state.
+    /// Can't be combined with `zero_on_alloc`, which would undo the ctor.
+    /// Must be called before the first allocation.
+    pub const fn with_ctor(mut self, ctor: fn(NonNull<u8>)) -> Self {
+        debug_assert!(self.partial.is_null() && self.full.is_null() && self.empty.is_null());
+        debug_assert!(!self.zero_objects);
+        self.ctor = Some(ctor);
+        self.compute_stride();
+        self.compute_page_layout();
+        self
+    }
+
+    /// Run `dtor` on every object of a slab before it goes back to the page provider.
+    /// Must be called before the first allocation.
+    pub const fn with_dtor(mut self, dtor: fn(NonNull<u8>)) -> Self {
+        debug_assert!(self.partial.is_null() && self.full.is_null() && self.empty.is_null());
+        self.dtor = Some(dtor);
+        self.compute_stride();
+        self.compute_page_layout();
+        self
+    }
+
+    /// Keep a per-object allocated bitmap in every page header so `try_free`
+    /// reports double frees instead of corrupting the free list.
+    /// Must be called before the first allocation.
+    pub const fn detect_double_free(mut self) -> Self {
+        debug_assert!(self.partial.is_null() && self.full.is_null() && self.empty.is_null());
+        self.check_double_free = true;
+        self.compute_page_layout();
+        self
+    }
+
+    /// Track free objects with a bitmap in every page header instead of free
+    /// lists threaded through the objects. Objects no longer have to hold a
+    /// pointer, so they can be smaller than one and keep the alignment asked
+    /// for, whether an object is handed out is a bit test, and double frees are
+    /// always caught. Allocation scans the bitmap for a free slot.
+    /// Must be called before the first allocation.
+    pub const fn track_with_bitmap(mut self) -> Self {
+        debug_assert!(self.partial.is_null() && self.full.is_null() && self.empty.is_null());
+        self.track_bitmap = true;
+        self.compute_stride();
+        self.compute_page_layout();
+        self
+    }
+
+    /// Hand out every object zeroed, like `alloc_zeroed`, including from
+    /// `alloc_bulk`. Zeroing would undo a ctor, so caches with one can't use
+    /// this, in either order. Must be called before the first allocation.
+    pub const fn zero_on_alloc(mut self) -> Self {
+        debug_assert!(self.partial.is_null() && self.full.is_null() && self.empty.is_null());
+        debug_assert!(self.ctor.is_none());
+        self.zero_objects = true;
+        self
+    }
+
+    /// Shift the first object of each new slab by a rotating multiple of
+    /// `step` bytes, taken from the space left over at the end of the slab, so
+    /// objects at the same index in different slabs don't all land in the same
+    /// cache sets. `step` is rounded up to the object alignment; the cache line
+    /// size is the usual choice. Must be called before the first allocation.
+    pub const fn with_coloring(mut self, step: usize) -> Self {
+        debug_assert!(self.partial.is_null() && self.full.is_null() && self.empty.is_null());
+        self.color_step = (step + self.align - 1) & !(self.align - 1);
+        self
+    }
+
+    /// Make `alloc` fail on purpose according to `mode`, on top of any real
+    /// failures, to test how callers cope with running out of memory.
+    pub const fn with_fault_injection(mut self, mode: FaultInjection) -> Self {
+        self.fault = Some(FaultInjector::new(mode));
+        self
+    }
+
+    /// Switch fault injection on, restarting its count, or off with `None`.
+    /// Unlike `with_fault_injection`, this can be done at any time.
+    pub fn set_fault_injection(&mut self, mode: Option<FaultInjection>) {
+        self.fault = mode.map(FaultInjector::new);
+    }
+
+    /// Take pages from `provider` instead of the current page source.
+    /// Must be called before the first allocation.
+    pub fn with_provider<Q: PageProvider>(self, provider: Q) -> SlabAllocator<Q> {
+        debug_assert!(self.partial.is_null() && self.full.is_null() && self.empty.is_null());
+        let page_size = provider.page_size();
+        let mut slab =
+            SlabAllocator::from_size_align(self.size, self.layout_align, provider, page_size);
+        slab.check_double_free = self.check_double_free;
+        slab.track_bitmap = self.track_bitmap;
+        slab.zero_objects = self.zero_objects;
+        slab.ctor = self.ctor;
+        slab.dtor = self.dtor;
+        slab.color_step = self.color_step;
+        slab.fault = self.fault;
+        slab.compute_stride();
+        slab.compute_page_layout();
+        slab
+    }
+
+    /// Take pages from `region` instead of the shared static pool.
+    /// Must be called before the first allocation.
+    pub fn in_region(self, region: &'static mut [u8]) -> SlabAllocator<PagePool> {
+        self.with_provider(PagePool::from_region(region))
+    }
+
+    // Work out the stride and where the free list link lives in each object
+    const fn compute_stride(&mut self) {
+        // Without a link, objects only need room for themselves
+        if self.track_bitmap {
+            let size = if self.size == 0 { 1 } else { self.size };
+            self.align = self.layout_align;
+            self.object_size = (size + self.align - 1) & !(self.align - 1);
+            self.link_offset = 0;
+            return;
+        }
+
+        // The free list pointer lives inside free objects, so never go below its alignment
+        self.align = if self.layout_align < core::mem::align_of::<FreeObject>() {
+            core::mem::align_of::<FreeObject>()
+        } else {
+            self.layout_align
+        };
+        let pointer_size = core::mem::size_of::<*mut FreeObject>();
+
+        // Constructed state has to survive on the free list, so the link goes after the object
+        let link_offset = if self.ctor.is_some() || self.dtor.is_some() {
+            (self.size + pointer_size - 1) & !(pointer_size - 1)
+        } else {
+            0
+        };
+
         // Make sure objects are at least pointer-sized (needed for free list)
-        let object_size = object_size.max(core::mem::size_of::<*mut FreeObject>());
+        let object_size = if self.size < link_offset + pointer_size {
+            link_offset + pointer_size
+        } else {
+            self.size
+        };
+
+        // Round the stride up to the alignment so every object stays aligned
+        self.object_size = (object_size + self.align - 1) & !(self.align - 1);
+        self.link_offset = link_offset;
+    }
 
-        // Align to pointer size
-        let object_size = (object_size + core::mem::align_of::<*mut FreeObject>() - 1)
-            & !(core::mem::align_of::<*mut FreeObject>() - 1);
+    // Pick the slab order and work out where objects start and how many fit.
+    // The smallest order wasting at most 1/8 of the slab wins, otherwise the
+    // order wasting the least, so large objects get multi-page slabs.
+    const fn compute_page_layout(&mut self) {
+        // Slabs are only page-aligned, so no object in them could be aligned further
+        if self.align > self.page_size {
+            self.data_offset = 0;
+            self.objects_per_slab = 0;
+            self.bitmap_words = 0;
+            self.slab_pages = 1;
+            return;
+        }
+
+        let mut best = self.fit(self.page_size);
+        let mut best_order = 0;
+        let mut order = 1;
+        while order <= MAX_ORDER {
+            // Good enough once at most 1/8 of the slab is wasted
+            if best.1 != 0 && self.waste(best, best_order) * 8 <= self.page_size << best_order {
+                break;
+            }
+
+            // Compare waste ratios without dividing
+            let slab = self.fit(self.page_size << order);
+            if slab.1 != 0
+                && (best.1 == 0
+                    || self.waste(slab, order) << best_order
+                        < self.waste(best, best_order) << order)
+            {
+                best = slab;
+                best_order = order;
+            }
+            order += 1;
+        }
+
+        let (data_offset, objects_per_slab, bitmap_words) = best;
+        self.data_offset = data_offset;
+        self.objects_per_slab = objects_per_slab;
+        self.bitmap_words = bitmap_words;
+        self.slab_pages = 1 << best_order;
+    }
 
-        // Count how many objects fit in one page
+    // Data offset, object count and bitmap words for a slab of slab_size bytes
+    const fn fit(&self, slab_size: usize) -> (usize, usize, usize) {
+        // Count how many objects fit in one slab
         // Account for page header and alignment padding
         let header_size = core::mem::size_of::<PageHeader>();
-        let object_align = core::mem::align_of::<FreeObject>();
-        let data_start_raw = header_size;
-        let aligned_start = (data_start_raw + object_align - 1) & !(object_align - 1);
-        let usable_space = PAGE_SIZE - aligned_start;
-        let objects_per_page = usable_space / object_size;
+        let align = self.align;
+        let mut objects =
+            slab_size.saturating_sub((header_size + align - 1) & !(align - 1)) / self.object_size;
+
+        // The bitmap eats into the data area, so shrink until everything fits
+        loop {
+            let bitmap_words = if self.check_double_free || self.track_bitmap {
+                objects.div_ceil(u64::BITS as usize)
+            } else {
+                0
+            };
+            let bitmap_end = header_size + bitmap_words * core::mem::size_of::<u64>();
+            let data_offset = (bitmap_end + align - 1) & !(align - 1);
+            if objects == 0 || data_offset + objects * self.object_size <= slab_size {
+                return (data_offset, objects, bitmap_words);
+            }
+            objects -= 1;
+        }
+    }
+
+    // Bytes of an order-`order` slab not covered by objects
+    const fn waste(&self, fit: (usize, usize, usize), order: usize) -> usize {
+        (self.page_size << order) - fit.1 * self.object_size
+    }
+
+    // Bytes in one slab
+    const fn slab_size(&self) -> usize {
+        self.page_size * self.slab_pages
+    }
 
-        Self {
-            object_size,
-            objects_per_page,
-            free_list: core::ptr::null_mut(),
-            pages: core::ptr::null_mut(),
+    // Bytes left after the last object of an uncolored slab, the room coloring has
+    const fn tail(&self) -> usize {
+        self.slab_size()
+            .saturating_sub(self.data_offset + self.objects_per_slab * self.object_size)
+    }
+
+    // Color of the next new slab, cycling through every offset the tail allows
+    fn take_color(&mut self) -> usize {
+        let color = self.next_color;
+        self.next_color = if self.color_step == 0 || color + self.color_step > self.tail() {
+            0
+        } else {
+            color + self.color_step
+        };
+        color
+    }
+
+    // Address of the first object of page
+    // SAFETY: page must be one of ours.
+    unsafe fn data_start(&self, page: *mut Page) -> *mut u8 {
+        unsafe { (page as *mut u8).add(self.data_offset + (*page).color) }
+    }
+
+    /// Bytes each object takes up in a slab, after rounding.
+    pub const fn object_size(&self) -> usize {
+        self.object_size
+    }
+
+    /// Alignment guaranteed for every object handed out by this cache.
+    pub const fn align(&self) -> usize {
+        self.align
+    }
+
+    /// Counters and page usage of this cache.
+    pub fn stats(&self) -> SlabStats {
+        let live = self.allocated - self.freed;
+        SlabStats {
+            allocated: self.allocated,
+            freed: self.freed,
+            failed: self.failed,
+            live,
+            high_water: self.high_water,
+            free: self.slabs * self.objects_per_slab - live,
+            pages: self.slabs * self.slab_pages,
+            wasted_bytes: self.slabs
+                * (self.slab_size() - self.objects_per_slab * self.object_size),
         }
     }
 
+    /// Allocate an object, or `None` if the cache can't; `try_alloc` says why.
     pub fn alloc(&mut self) -> Option<NonNull<u8>> {
-        // SAFETY: free_list is non-null after allocate_page, and points to valid memory from our pool.
+        self.try_alloc().ok()
+    }
+
+    /// Allocate an object, reporting why when none can be handed out.
+    pub fn try_alloc(&mut self) -> Result<NonNull<u8>, AllocError> {
+        self.alloc_object(self.zero_objects)
+    }
+
+    /// Allocate an object with every byte zeroed. Objects of pages still
+    /// fresh from a zeroed pool, such as the static one, are not cleared
+    /// again. Zeroing would undo a ctor, so caches with one shouldn't use this.
+    pub fn alloc_zeroed(&mut self) -> Option<NonNull<u8>> {
+        debug_assert!(self.ctor.is_none());
+        self.alloc_object(true).ok()
+    }
+
+    // Allocate an object, zeroing it first if asked to
+    fn alloc_object(&mut self, zero: bool) -> Result<NonNull<u8>, AllocError> {
+        if let Some(fault) = &mut self.fault
+            && fault.should_fail()
+        {
+            self.failed += 1;
+            return Err(AllocError::InjectedFault);
+        }
+
+        // SAFETY: partial is non-null after refill, and its pages point to valid memory from our pool.
         unsafe {
-            // If free list is empty, allocate a new page
-            if self.free_list.is_null() {
-                self.allocate_page()?;
+            // Allocate from partial pages first to keep memory dense
+            if self.partial.is_null()
+                && let Err(err) = self.refill()
+            {
+                self.failed += 1;
+                return Err(err);
             }
 
-            // Pop from free list
-            let obj = self.free_list;
-            self.free_list = (*obj).next;
-
-            Some(NonNull::new_unchecked(obj as *mut u8))
+            let mut obj = MaybeUninit::uninit();
+            self.take_from_page(self.partial, core::slice::from_mut(&mut obj), zero);
+            Ok(obj.assume_init())
         }
     }
 
-    /// Free an object, returning it to the free list.
+    /// Free an object, returning it to its page's free list.
+    /// Pointers that `try_free` rejects are ignored.
     pub fn free(&mut self, ptr: NonNull<u8>) {
-        // SAFETY: ptr is validated to be within our pool before dereferencing.
+        let _ = self.try_free(ptr);
+    }
+
+    /// Free an object, checking first that it was handed out by this cache.
+    pub fn try_free(&mut self, ptr: NonNull<u8>) -> Result<(), FreeError> {
+        let (page, index) = self.slot_of(ptr)?;
+
+        // SAFETY: slot_of only returns our pages and in-range indices, so ptr
+        // is the start of an object in one of our pages.
+        unsafe {
+            let was_full = (*page).live == self.objects_per_slab;
+            self.mark_free(page, index)?;
+
+            // The cleared bit is all a bitmap-tracked page needs
+            if !self.track_bitmap {
+                let free_obj = ptr.as_ptr().add(self.link_offset) as *mut FreeObject;
+                (*free_obj).next = (*page).free_list;
+                (*page).free_list = free_obj;
+            }
+            self.settle_freed(page, was_full);
+        }
+        Ok(())
+    }
+
+    /// Allocate up to `objects.len()` objects into `objects`, returning how
+    /// many were written; they fill the front of the slice. Runs of objects
+    /// are detached from a slab's free list in one go rather than popped one
+    /// at a time. Fault injection is consulted for every object.
+    pub fn alloc_bulk(&mut self, objects: &mut [MaybeUninit<NonNull<u8>>]) -> usize {
+        let mut filled = 0;
+        while filled < objects.len() {
+            if self.partial.is_null() {
+                // SAFETY: refill only links our own pages.
+                if unsafe { self.refill() }.is_err() {
+                    self.failed += 1;
+                    break;
+                }
+            }
+
+            // Take as much of the page as the request and fault injection allow
+            let page = self.partial;
+            // SAFETY: page is on our partial list.
+            let available = self.objects_per_slab - unsafe { (*page).live };
+            let wanted = available.min(objects.len() - filled);
+            let granted = self.injection_allows(wanted);
+
+            // SAFETY: page is ours with at least `granted` free objects.
+            unsafe {
+                self.take_from_page(
+                    page,
+                    &mut objects[filled..filled + granted],
+                    self.zero_objects,
+                )
+            };
+            filled += granted;
+            if granted < wanted {
+                self.failed += 1;
+                break;
+            }
+        }
+        filled
+    }
+
+    /// Free every object in `objects`. Consecutive objects from the same slab
+    /// are linked into a chain and spliced onto its free list at once, so
+    /// batches freed in allocation order touch each slab once. Pointers that
+    /// `try_free` would reject are skipped.
+    pub fn free_bulk(&mut self, objects: &[NonNull<u8>]) {
+        let mut rest = objects;
+        while let Some(&first) = rest.first() {
+            let Some(page) = self.page_of(first.as_ptr() as usize) else {
+                rest = &rest[1..];
+                continue;
+            };
+
+            let start = page as usize;
+            let run = rest
+                .iter()
+                .take_while(|ptr| {
+                    (start..start + self.slab_size()).contains(&(ptr.as_ptr() as usize))
+                })
+                .count();
+            let (batch, tail) = rest.split_at(run);
+            rest = tail;
+            // SAFETY: page is one of ours, and the batch lies inside it.
+            unsafe { self.free_run(page, batch) };
+        }
+    }
+
+    /// Whether `ptr` is currently handed out, or `None` if it isn't an object
+    /// of this cache. Finding the slab walks the slab lists, so the cost grows
+    /// with the number of slabs. With a bitmap, from `track_with_bitmap` or
+    /// `detect_double_free`, the answer is then a single bit test; otherwise
+    /// the slab's free list is walked as well.
+    pub fn is_allocated(&self, ptr: NonNull<u8>) -> Option<bool> {
+        let (page, index) = self.slot_of(ptr).ok()?;
+        // SAFETY: slot_of only returns our pages and in-range indices.
+        Some(unsafe { self.slot_allocated(page, index) })
+    }
+
+    /// Give every cached empty slab back to the page provider, for example
+    /// under memory pressure, and return how many pages were released.
+    /// Slabs with live objects are kept.
+    pub fn shrink(&mut self) -> usize {
+        let mut released = 0;
+        while !self.empty.is_null() {
+            let page = self.empty;
+            // SAFETY: Pages on the empty list are ours and have no live objects.
+            unsafe {
+                list_remove(&mut self.empty, page);
+                self.release_slab(page);
+            }
+            self.empty_pages -= 1;
+            released += self.slab_pages;
+        }
+        released
+    }
+
+    /// Iterate over the objects currently handed out, slab by slab.
+    pub fn live_objects(&self) -> LiveObjects<'_, P> {
+        LiveObjects::new(self)
+    }
+
+    /// Walk the slabs of this cache, partial ones first, then full, then
+    /// cached empty ones, with the occupancy of each.
+    pub fn pages(&self) -> Pages<'_, P> {
+        Pages::new(self)
+    }
+
+    /// Walk every object slot of every slab, in the order of `pages`, with
+    /// whether it is handed out. Without a bitmap each slot is looked up on
+    /// its slab's free list, so this is meant for debugging.
+    pub fn objects(&self) -> Objects<'_, P> {
+        Objects::new(self)
+    }
+
+    // Page and index of the object at ptr, checking it sits on an object boundary
+    fn slot_of(&self, ptr: NonNull<u8>) -> Result<(*mut Page, usize), FreeError> {
+        let page = self
+            .page_of(ptr.as_ptr() as usize)
+            .ok_or(FreeError::ForeignPointer)?;
+        // SAFETY: page_of only returns our pages.
+        Ok((page, unsafe { self.slot_in(page, ptr) }?))
+    }
+
+    // Index of the object at ptr within page, checking it sits on an object boundary
+    // SAFETY: page must be one of ours.
+    unsafe fn slot_in(&self, page: *mut Page, ptr: NonNull<u8>) -> Result<usize, FreeError> {
+        let ptr_addr = ptr.as_ptr() as usize;
+
+        // Must sit exactly on data_start + k * object_size
+        // SAFETY: page is one of ours, so its header is valid.
+        let data_start = unsafe { self.data_start(page) } as usize;
+        if ptr_addr < data_start {
+            return Err(FreeError::Misaligned);
+        }
+        let offset = ptr_addr - data_start;
+        if !offset.is_multiple_of(self.object_size)
+            || offset / self.object_size >= self.objects_per_slab
+        {
+            return Err(FreeError::Misaligned);
+        }
+        Ok(offset / self.object_size)
+    }
+
+    // Check object `index` of page is handed out and count it as free; the
+    // caller links it back unless the page is bitmap-tracked
+    // SAFETY: page must be one of ours and index below objects_per_slab.
+    unsafe fn mark_free(&mut self, page: *mut Page, index: usize) -> Result<(), FreeError> {
+        unsafe {
+            if (*page).live == 0 {
+                // Nothing on an empty page is handed out
+                return Err(FreeError::DoubleFree);
+            }
+
+            if self.bitmap_words != 0 {
+                let (word, bit) = self.bitmap_slot(page, index);
+                if *word & bit == 0 {
+                    return Err(FreeError::DoubleFree);
+                }
+                *word &= !bit;
+            }
+
+            (*page).live -= 1;
+        }
+        self.freed += 1;
+        Ok(())
+    }
+
+    // Move page to the list matching its live count after objects were freed
+    // SAFETY: page must be one of ours, on the full list if was_full, else on partial.
+    unsafe fn settle_freed(&mut self, page: *mut Page, was_full: bool) {
+        unsafe {
+            if (*page).live == 0 {
+                let list = if was_full {
+                    &mut self.full
+                } else {
+                    &mut self.partial
+                };
+                list_remove(list, page);
+                self.retire_page(page);
+            } else if was_full && (*page).live < self.objects_per_slab {
+                list_remove(&mut self.full, page);
+                list_push(&mut self.partial, page);
+            }
+        }
+    }
+
+    // How many of the next `wanted` allocations fault injection lets through
+    fn injection_allows(&mut self, wanted: usize) -> usize {
+        match &mut self.fault {
+            Some(fault) => (0..wanted).find(|_| fault.should_fail()).unwrap_or(wanted),
+            None => wanted,
+        }
+    }
+
+    // Hand out objects.len() objects of page, popping its free list or taking
+    // its first clear bitmap slots, and detaching them as one chain
+    // SAFETY: page must be on the partial list with at least objects.len() free objects.
+    unsafe fn take_from_page(
+        &mut self,
+        page: *mut Page,
+        objects: &mut [MaybeUninit<NonNull<u8>>],
+        zero: bool,
+    ) {
         unsafe {
-            let pool_start = addr_of_mut!(PAGE_POOL) as *const u8 as usize;
-            let pool_end = pool_start + MAX_PAGES * PAGE_SIZE;
+            let mut node = (*page).free_list;
+            for slot in objects.iter_mut() {
+                let obj = if self.track_bitmap {
+                    self.data_start(page)
+                        .add(self.first_free_slot(page) * self.object_size)
+                } else {
+                    let obj = (node as *mut u8).sub(self.link_offset);
+                    node = (*node).next;
+                    obj
+                };
+                // Bitmap-tracked pages find the next slot through this bit
+                if self.bitmap_words != 0 {
+                    let (word, bit) = self.bitmap_slot(page, self.object_index(page, obj as usize));
+                    *word |= bit;
+                }
+                self.prepare_object(page, obj, zero);
+                (*page).live += 1;
+                slot.write(NonNull::new_unchecked(obj));
+            }
+            (*page).free_list = node;
+
+            if (*page).live == self.objects_per_slab {
+                list_remove(&mut self.partial, page);
+                list_push(&mut self.full, page);
+            }
+        }
 
-            let ptr_addr = ptr.as_ptr() as usize;
+        self.allocated += objects.len();
+        self.high_water = self.high_water.max(self.allocated - self.freed);
+    }
 
-            // If pointer is outside the pool, ignore
-            if ptr_addr < pool_start || ptr_addr >= pool_end {
+    // Zero obj if asked to, and mark it as no longer pristine. A pristine
+    // object only needs its free list link cleared.
+    // SAFETY: obj must be an object of page that is being handed out.
+    unsafe fn prepare_object(&mut self, page: *mut Page, obj: *mut u8, zero: bool) {
+        unsafe {
+            // Once every object was handed out, none of them can be pristine
+            let pristine = (*page).pristine_from != self.objects_per_slab
+                && self.object_index(page, obj as usize) >= (*page).pristine_from;
+            if !pristine {
+                if zero {
+                    ptr::write_bytes(obj, 0, self.size);
+                }
                 return;
             }
 
-            let free_obj = ptr.as_ptr() as *mut FreeObject;
-            (*free_obj).next = self.free_list;
-            self.free_list = free_obj;
+            if zero && !self.track_bitmap {
+                (*(obj.add(self.link_offset) as *mut FreeObject)).next = ptr::null_mut();
+            }
+            (*page).pristine_from = self.object_index(page, obj as usize) + 1;
         }
     }
 
-    unsafe fn allocate_page(&mut self) -> Option<()> {
-        // Check if we have space for another page
-        // SAFETY: Reading mutable static is safe because we're the only allocator.
+    // Free a batch of objects lying in page, splicing their links onto its
+    // free list as one chain
+    // SAFETY: page must be one of ours.
+    unsafe fn free_run(&mut self, page: *mut Page, batch: &[NonNull<u8>]) {
         unsafe {
-            if PAGE_POOL_USED + PAGE_SIZE > MAX_PAGES * PAGE_SIZE {
-                return None;
+            let was_full = (*page).live == self.objects_per_slab;
+            let live = (*page).live;
+            let mut head = (*page).free_list;
+            for &ptr in batch {
+                let Ok(index) = self.slot_in(page, ptr) else {
+                    continue;
+                };
+                if self.mark_free(page, index).is_err() {
+                    continue;
+                }
+                if !self.track_bitmap {
+                    let free_obj = ptr.as_ptr().add(self.link_offset) as *mut FreeObject;
+                    (*free_obj).next = head;
+                    head = free_obj;
+                }
+            }
+            // A page whose pointers were all rejected may sit on the empty list
+            if (*page).live == live {
+                return;
+            }
+            (*page).free_list = head;
+            self.settle_freed(page, was_full);
+        }
+    }
+
+    // Whether object `index` of page is handed out
+    // SAFETY: page must be one of ours and index below objects_per_slab.
+    unsafe fn slot_allocated(&self, page: *mut Page, index: usize) -> bool {
+        unsafe {
+            if self.bitmap_words != 0 {
+                let (word, bit) = self.bitmap_slot(page, index);
+                return *word & bit != 0;
+            }
+
+            // Otherwise it's handed out unless it's on the page's free list
+            let link = self
+                .data_start(page)
+                .add(index * self.object_size + self.link_offset)
+                as *mut FreeObject;
+            let mut free = (*page).free_list;
+            while !free.is_null() {
+                if free == link {
+                    return false;
+                }
+                free = (*free).next;
+            }
+            true
+        }
+    }
+
+    // Index of the first clear bit in page's bitmap
+    // SAFETY: page must be one of ours, bitmap-tracked and not full.
+    unsafe fn first_free_slot(&self, page: *mut Page) -> usize {
+        let bits = u64::BITS as usize;
+        for i in 0..self.bitmap_words {
+            // SAFETY: The bitmap holds bitmap_words words after the header.
+            let (word, _) = unsafe { self.bitmap_slot(page, i * bits) };
+            let word = unsafe { *word };
+            if word != u64::MAX {
+                // Bits past the last object stay clear, but a free object comes first
+                return i * bits + (!word).trailing_zeros() as usize;
+            }
+        }
+        unreachable!("page on the partial list has no free slot")
+    }
+
+    // Index of the object at addr within page
+    fn object_index(&self, page: *mut Page, addr: usize) -> usize {
+        // SAFETY: Only called with pages from our lists.
+        (addr - unsafe { self.data_start(page) } as usize) / self.object_size
+    }
+
+    // Bitmap word and bit tracking object `index` of page
+    // SAFETY: page must be one of ours and bitmap_words non-zero.
+    unsafe fn bitmap_slot(&self, page: *mut Page, index: usize) -> (*mut u64, u64) {
+        let bits = u64::BITS as usize;
+        // SAFETY: The bitmap follows the header and holds one bit per object.
+        let word = unsafe {
+            (page as *mut u8)
+                .add(core::mem::size_of::<PageHeader>())
+                .cast::<u64>()
+                .add(index / bits)
+        };
+        (word, 1 << (index % bits))
+    }
+
+    // Find the page on one of our lists that contains addr
+    fn page_of(&self, addr: usize) -> Option<*mut Page> {
+        for list in [self.partial, self.full, self.empty] {
+            let mut page = list;
+            while !page.is_null() {
+                let start = page as usize;
+                if addr >= start && addr < start + self.slab_size() {
+                    return Some(page);
+                }
+                // SAFETY: Every page on our lists has a header written by allocate_page.
+                page = unsafe { (*page).next };
             }
         }
+        None
+    }
+
+    // Make a page with free objects available on the partial list
+    unsafe fn refill(&mut self) -> Result<(), AllocError> {
+        // Objects too large for even the biggest slab
+        if self.objects_per_slab == 0 {
+            return Err(AllocError::ObjectTooLarge);
+        }
 
-        // Get the next page from the pool
-        let pool_start = addr_of_mut!(PAGE_POOL) as *mut u8;
-        // SAFETY: PAGE_POOL_USED is within bounds, and add stays within PAGE_POOL array.
-        let page_ptr = unsafe { pool_start.add(PAGE_POOL_USED) } as *mut Page;
-        // SAFETY: Writing to mutable static is safe because we're the only allocator.
+        // SAFETY: Pages on the empty list are ours and have every object free.
         unsafe {
-            PAGE_POOL_USED += PAGE_SIZE;
+            let page = if !self.empty.is_null() {
+                let page = self.empty;
+                list_remove(&mut self.empty, page);
+                self.empty_pages -= 1;
+                page
+            } else {
+                self.allocate_page().ok_or(AllocError::PoolExhausted)?
+            };
+            list_push(&mut self.partial, page);
         }
+        Ok(())
+    }
+
+    unsafe fn allocate_page(&mut self) -> Option<*mut Page> {
+        let (pages, zeroed) = self.provider.acquire_zeroed_pages(self.slab_pages)?;
+        let page_ptr = pages.as_ptr() as *mut Page;
+        let color = self.take_color();
+
+        // A ctor writes every object, so none of them stays zeroed
+        let pristine_from = if zeroed && self.ctor.is_none() {
+            0
+        } else {
+            self.objects_per_slab
+        };
 
-        // Write the page header
-        // SAFETY: page_ptr points to valid memory within PAGE_POOL that we just allocated.
+        // Write the page header, with every object marked free in the bitmap
+        // SAFETY: page_ptr points to a page the provider just handed us.
         unsafe {
-            ptr::write(page_ptr, Page { next: self.pages });
+            ptr::write(
+                page_ptr,
+                Page {
+                    next: ptr::null_mut(),
+                    prev: ptr::null_mut(),
+                    free_list: ptr::null_mut(),
+                    live: 0,
+                    color,
+                    pristine_from,
+                },
+            );
+            let bitmap = (page_ptr as *mut u8).add(core::mem::size_of::<PageHeader>());
+            ptr::write_bytes(bitmap, 0, self.bitmap_words * core::mem::size_of::<u64>());
         }
 
-        // The data area starts after the header, aligned to object alignment
-        let header_size = core::mem::size_of::<PageHeader>();
-        let object_align = core::mem::align_of::<FreeObject>();
-        let data_start_raw = unsafe { (page_ptr as *mut u8).add(header_size) };
-        // Align data_start to object alignment requirement
-        let data_start_addr = data_start_raw as usize;
-        let aligned_addr = (data_start_addr + object_align - 1) & !(object_align - 1);
-        let data_start = aligned_addr as *mut u8;
-        
-        // SAFETY: data_start is aligned and within page bounds, and object_size accounts for alignment.
-        for i in 0..self.objects_per_page {
-            // SAFETY: i * object_size is bounded by objects_per_page calculation, and data_start is aligned.
-            let obj_ptr = unsafe { data_start.add(i * self.object_size) } as *mut FreeObject;
+        // The data area starts after the header, aligned to object alignment and
+        // shifted by the slab's color. Pages are page-aligned, so the offset
+        // computed in `new` is enough.
+        // SAFETY: data_offset + color is below the slab size whenever objects_per_slab is non-zero.
+        let data_start = unsafe { (page_ptr as *mut u8).add(self.data_offset + color) };
+
+        // Thread the objects in reverse so the page hands them out in address order
+        // SAFETY: data_start is aligned and within slab bounds, and object_size accounts for alignment.
+        for i in (0..self.objects_per_slab).rev() {
+            // SAFETY: i * object_size is bounded by objects_per_slab calculation, and data_start is aligned.
+            let obj_ptr = unsafe { data_start.add(i * self.object_size) };
             // SAFETY: obj_ptr is properly aligned and points to valid memory within the page we just allocated.
             unsafe {
-                (*obj_ptr).next = self.free_list;
+                if let Some(ctor) = self.ctor {
+                    ctor(NonNull::new_unchecked(obj_ptr));
+                }
+                // Bitmap-tracked pages start with every bit clear instead
+                if self.track_bitmap {
+                    continue;
+                }
+                let free_obj = obj_ptr.add(self.link_offset) as *mut FreeObject;
+                (*free_obj).next = (*page_ptr).free_list;
+                (*page_ptr).free_list = free_obj;
             }
-            self.free_list = obj_ptr;
         }
 
-        self.pages = page_ptr;
-        Some(())
+        self.slabs += 1;
+        Some(page_ptr)
     }
-    // SAFETY: Resetting the pool is safe because we're the only allocator.
-    pub fn reset_pool() {
+
+    // Park a page that just became empty, or give it back if enough are cached
+    // SAFETY: page must be ours, unlinked from every list, with no live objects.
+    unsafe fn retire_page(&mut self, page: *mut Page) {
+        unsafe {
+            if self.empty_pages < MAX_EMPTY_PAGES {
+                list_push(&mut self.empty, page);
+                self.empty_pages += 1;
+            } else {
+                self.release_slab(page);
+            }
+        }
+    }
+
+    // Destroy a slab's objects and hand its pages back to the provider
+    // SAFETY: page must be ours, unlinked from every list, with no live objects.
+    unsafe fn release_slab(&mut self, page: *mut Page) {
         unsafe {
-            PAGE_POOL_USED = 0;
+            if let Some(dtor) = self.dtor {
+                let data_start = self.data_start(page);
+                for i in 0..self.objects_per_slab {
+                    dtor(NonNull::new_unchecked(data_start.add(i * self.object_size)));
+                }
+            }
+            self.provider
+                .release_pages(NonNull::new_unchecked(page as *mut u8), self.slab_pages);
+        }
+        self.slabs -= 1;
+    }
+}
+
+impl<P: PageProvider> Drop for SlabAllocator<P> {
+    fn drop(&mut self) {
+        // Slabs with live objects are leaked, so their objects stay valid
+        self.shrink();
+    }
+}
+
+// Push page at the head of a page list
+// SAFETY: page must be a valid header not on any list.
+unsafe fn list_push(head: &mut *mut Page, page: *mut Page) {
+    unsafe {
+        (*page).prev = ptr::null_mut();
+        (*page).next = *head;
+        if !(*head).is_null() {
+            (**head).prev = page;
+        }
+    }
+    *head = page;
+}
+
+// Unlink page from the list starting at head
+// SAFETY: page must be on that list.
+unsafe fn list_remove(head: &mut *mut Page, page: *mut Page) {
+    unsafe {
+        if (*page).prev.is_null() {
+            *head = (*page).next;
+        } else {
+            (*(*page).prev).next = (*page).next;
+        }
+        if !(*page).next.is_null() {
+            (*(*page).next).prev = (*page).prev;
         }
     }
 }
 
+// Tests share PAGE_POOL, so they take turns and start from an empty pool.
+#[cfg(test)]
+static TEST_POOL_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+#[cfg(test)]
+pub(crate) fn lock_pool() -> std::sync::MutexGuard<'static, ()> {
+    let guard = TEST_POOL_LOCK
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    SlabAllocator::reset_pool();
+    guard
+}
+
+// Leaked buffer of exactly `pages` page-aligned pages, for region-backed tests
+#[cfg(test)]
+pub(crate) fn test_region(pages: usize) -> &'static mut [u8] {
+    let buffer = std::vec![0u8; (pages + 1) * PAGE_SIZE].leak();
+    let offset = buffer.as_ptr().align_offset(PAGE_SIZE);
+    &mut buffer[offset..offset + pages * PAGE_SIZE]
+}
+
 // SAFETY: This function is required by the C runtime ABI.
 // It is not meant to be called directly; it exists only so the linker can resolve the symbol.
 #[cfg(not(test))]
@@ -178,8 +1090,137 @@ pub extern "C" fn abort() {
     exit(1);
 }
 
+// SAFETY: These memory routines are required by the code the compiler emits for `alloc`.
+// Nothing links libc, so we provide them. LLVM recognizes plain copy and fill loops and
+// compiles them into calls to memcpy and memset, which here would call themselves forever,
+// so every access is volatile. Words are moved whenever both pointers can be aligned to a
+// word together, with bytes only for the unaligned ends.
+#[cfg(not(test))]
+const WORD: usize = core::mem::size_of::<usize>();
+
+// Whether dest and src reach word alignment after the same number of bytes
+#[cfg(not(test))]
+fn co_aligned(dest: *const u8, src: *const u8) -> bool {
+    (dest as usize ^ src as usize).is_multiple_of(WORD)
+}
+
+#[cfg(not(test))]
+#[unsafe(no_mangle)]
+unsafe extern "C" fn memset(dest: *mut u8, value: i32, n: usize) -> *mut u8 {
+    let byte = value as u8;
+    let mut i = 0;
+    unsafe {
+        while i < n && !(dest.add(i) as usize).is_multiple_of(WORD) {
+            ptr::write_volatile(dest.add(i), byte);
+            i += 1;
+        }
+        // The byte in every lane; an array of bytes would be filled with memset itself
+        let pattern = byte as usize * (usize::MAX / 0xff);
+        while i + WORD <= n {
+            ptr::write_volatile(dest.add(i) as *mut usize, pattern);
+            i += WORD;
+        }
+        while i < n {
+            ptr::write_volatile(dest.add(i), byte);
+            i += 1;
+        }
+    }
+    dest
+}
+
+#[cfg(not(test))]
+#[unsafe(no_mangle)]
+unsafe extern "C" fn memcpy(dest: *mut u8, src: *const u8, n: usize) -> *mut u8 {
+    let mut i = 0;
+    unsafe {
+        if co_aligned(dest, src) {
+            while i < n && !(dest.add(i) as usize).is_multiple_of(WORD) {
+                ptr::write_volatile(dest.add(i), ptr::read_volatile(src.add(i)));
+                i += 1;
+            }
+            while i + WORD <= n {
+                let word = ptr::read_volatile(src.add(i) as *const usize);
+                ptr::write_volatile(dest.add(i) as *mut usize, word);
+                i += WORD;
+            }
+        }
+        while i < n {
+            ptr::write_volatile(dest.add(i), ptr::read_volatile(src.add(i)));
+            i += 1;
+        }
+    }
+    dest
+}
+
+#[cfg(not(test))]
+#[unsafe(no_mangle)]
+unsafe extern "C" fn memmove(dest: *mut u8, src: *const u8, n: usize) -> *mut u8 {
+    if (dest as usize) <= (src as usize) {
+        return unsafe { memcpy(dest, src, n) };
+    }
+    // Copy backwards so an overlapping tail is read before it is overwritten
+    let mut i = n;
+    unsafe {
+        if co_aligned(dest, src) {
+            while i > 0 && !(dest.add(i) as usize).is_multiple_of(WORD) {
+                i -= 1;
+                ptr::write_volatile(dest.add(i), ptr::read_volatile(src.add(i)));
+            }
+            while i >= WORD {
+                i -= WORD;
+                let word = ptr::read_volatile(src.add(i) as *const usize);
+                ptr::write_volatile(dest.add(i) as *mut usize, word);
+            }
+        }
+        while i > 0 {
+            i -= 1;
+            ptr::write_volatile(dest.add(i), ptr::read_volatile(src.add(i)));
+        }
+    }
+    dest
+}
+
+#[cfg(not(test))]
+#[unsafe(no_mangle)]
+unsafe extern "C" fn memcmp(a: *const u8, b: *const u8, n: usize) -> i32 {
+    let mut i = 0;
+    unsafe {
+        // Skip equal words, then find the first differing byte one at a time
+        if co_aligned(a, b) {
+            while i < n && !(a.add(i) as usize).is_multiple_of(WORD) {
+                if ptr::read_volatile(a.add(i)) != ptr::read_volatile(b.add(i)) {
+                    break;
+                }
+                i += 1;
+            }
+            while i + WORD <= n
+                && (a.add(i) as usize).is_multiple_of(WORD)
+                && ptr::read_volatile(a.add(i) as *const usize)
+                    == ptr::read_volatile(b.add(i) as *const usize)
+            {
+                i += WORD;
+            }
+        }
+        while i < n {
+            let (x, y) = (ptr::read_volatile(a.add(i)), ptr::read_volatile(b.add(i)));
+            if x != y {
+                return x as i32 - y as i32;
+            }
+            i += 1;
+        }
+    }
+    0
+}
+
+#[cfg(not(test))]
+#[unsafe(no_mangle)]
+unsafe extern "C" fn bcmp(a: *const u8, b: *const u8, n: usize) -> i32 {
+    unsafe { memcmp(a, b, n) }
+}
+
 // SAFETY: This is the program entry point in a no_std environment.
 // It is marked `no_mangle` so the linker can find it.
+#[cfg(not(test))]
 #[unsafe(no_mangle)]
 pub extern "C" fn main() {
     let mut slab = SlabAllocator::new(64);
@@ -201,6 +1242,13 @@ pub extern "C" fn main() {
     let _obj4 = slab.alloc();
     let _obj5 = slab.alloc();
 
+    // Heap collections go through the global slab allocator
+    let boxed = Box::new(42u64);
+    let mut values = Vec::with_capacity(4);
+    values.push(*boxed);
+    values.push(7);
+    drop(values);
+
     exit(0);
 }
 
@@ -222,16 +1270,20 @@ pub extern "C" fn rust_eh_personality() {}
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::iter::{ObjectInfo, PageState};
     use core::ptr::NonNull;
+    use std::sync::MutexGuard;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::vec;
     use std::vec::Vec;
 
-    fn reset_state() {
-        SlabAllocator::reset_pool();
+    fn reset_state() -> MutexGuard<'static, ()> {
+        lock_pool()
     }
 
     #[test]
     fn test_free_outside_pool() {
-        reset_state();
+        let _pool = reset_state();
         let mut slab = SlabAllocator::new(64);
 
         // Create a fake pointer outside the pool
@@ -246,8 +1298,891 @@ mod tests {
     }
 
     #[test]
-    fn test_alloc_after_free() {
-        reset_state();
+    fn test_try_free_rejects_other_cache() {
+        let _pool = reset_state();
+        let mut slab = SlabAllocator::new(64);
+        let mut other = SlabAllocator::new(32);
+
+        let ptr = slab.alloc().unwrap();
+        let foreign = other.alloc().unwrap();
+
+        assert_eq!(slab.try_free(foreign), Err(FreeError::ForeignPointer));
+        assert_eq!(slab.try_free(ptr), Ok(()));
+        assert_eq!(other.try_free(foreign), Ok(()));
+    }
+
+    #[test]
+    fn test_try_free_rejects_interior_pointer() {
+        let _pool = reset_state();
+        let mut slab = SlabAllocator::new(64);
+
+        let ptr = slab.alloc().unwrap();
+        let interior = NonNull::new(ptr.as_ptr().wrapping_add(8)).unwrap();
+        assert_eq!(slab.try_free(interior), Err(FreeError::Misaligned));
+
+        // The page header is not an object either
+        let header = NonNull::new((ptr.as_ptr() as usize & !(PAGE_SIZE - 1)) as *mut u8).unwrap();
+        assert_eq!(slab.try_free(header), Err(FreeError::Misaligned));
+
+        // Free list is intact: the next allocation is not the interior pointer
+        assert_ne!(slab.alloc(), Some(interior));
+    }
+
+    #[test]
+    fn test_double_free_detected() {
+        let _pool = reset_state();
+        let mut slab = SlabAllocator::new(64).detect_double_free();
+
+        let a = slab.alloc().unwrap();
+        let b = slab.alloc().unwrap();
+
+        assert_eq!(slab.try_free(a), Ok(()));
+        assert_eq!(slab.try_free(a), Err(FreeError::DoubleFree));
+
+        // The rejected free left the list alone, so a and b stay distinct owners
+        let c = slab.alloc().unwrap();
+        assert_eq!(c, a);
+        assert_ne!(slab.alloc().unwrap(), a);
+        assert_eq!(slab.try_free(b), Ok(()));
+    }
+
+    #[test]
+    fn test_double_free_bitmap_fits_in_page() {
+        let _pool = reset_state();
+        let plain = SlabAllocator::new(8);
+        let mut slab = SlabAllocator::new(8).detect_double_free();
+
+        assert!(slab.objects_per_slab < plain.objects_per_slab);
+        assert!(slab.data_offset + slab.objects_per_slab * slab.object_size <= PAGE_SIZE);
+
+        // Every object in a full page can be allocated and freed once
+        let ptrs: Vec<_> = (0..slab.objects_per_slab)
+            .map(|_| slab.alloc().unwrap())
+            .collect();
+        for ptr in ptrs {
+            assert_eq!(slab.try_free(ptr), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_empty_pages_return_to_pool() {
+        let _pool = reset_state();
+        let mut greedy = SlabAllocator::new(64);
+
+        // Drain the whole pool into one cache
+        let mut ptrs = Vec::new();
+        while let Some(ptr) = greedy.alloc() {
+            ptrs.push(ptr);
+        }
+        assert!(SlabAllocator::new(32).alloc().is_none());
+
+        for ptr in ptrs {
+            greedy.free(ptr);
+        }
+        assert!(greedy.partial.is_null() && greedy.full.is_null());
+        assert_eq!(greedy.empty_pages, MAX_EMPTY_PAGES);
+
+        // Every page but the cached ones is back in the pool for another cache
+        let mut other = SlabAllocator::new(32);
+        for _ in 0..(MAX_PAGES - MAX_EMPTY_PAGES) * other.objects_per_slab {
+            assert!(other.alloc().is_some());
+        }
+        assert!(other.alloc().is_none());
+    }
+
+    #[test]
+    fn test_shrink_releases_cached_empty_slabs() {
+        let _pool = reset_state();
+        let mut slab = SlabAllocator::new(64);
+        let per_slab = slab.objects_per_slab;
+
+        let ptrs: Vec<_> = (0..2 * per_slab + 1)
+            .map(|_| slab.alloc().unwrap())
+            .collect();
+        for &ptr in &ptrs[..per_slab] {
+            slab.free(ptr);
+        }
+        assert_eq!(StaticPool.stats().used_pages, 3);
+
+        // Only the empty slab goes, the full and partial ones stay
+        assert_eq!(slab.shrink(), 1);
+        assert_eq!(slab.shrink(), 0);
+        assert_eq!(StaticPool.stats().used_pages, 2);
+        assert_eq!(slab.stats().pages, 2);
+        assert_eq!(slab.is_allocated(ptrs[per_slab]), Some(true));
+
+        // The cache keeps working and refills from the pool
+        for &ptr in &ptrs[per_slab..] {
+            slab.free(ptr);
+        }
+        assert_eq!(slab.shrink(), 1);
+        assert_eq!(StaticPool.stats().used_pages, 0);
+        assert!(slab.alloc().is_some());
+    }
+
+    #[test]
+    fn test_alloc_bulk_spans_slabs() {
+        let _pool = reset_state();
+        let mut slab = SlabAllocator::new(64);
+        let per_slab = slab.objects_per_slab;
+
+        let mut out = vec![MaybeUninit::uninit(); 2 * per_slab + 3];
+        assert_eq!(slab.alloc_bulk(&mut out), out.len());
+        let ptrs: Vec<_> = out.iter().map(|obj| unsafe { obj.assume_init() }).collect();
+
+        let mut sorted = ptrs.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(sorted.len(), ptrs.len());
+        assert!(ptrs.iter().all(|&ptr| slab.is_allocated(ptr) == Some(true)));
+
+        let stats = slab.stats();
+        assert_eq!(stats.allocated, ptrs.len());
+        assert_eq!(stats.high_water, ptrs.len());
+        assert_eq!(stats.pages, 3);
+        assert_eq!(
+            slab.pages()
+                .filter(|page| page.state == PageState::Full)
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_alloc_bulk_stops_when_pool_runs_out() {
+        let mut slab = SlabAllocator::new(64).in_region(test_region(2));
+        let per_slab = slab.objects_per_slab;
+
+        let mut out = vec![MaybeUninit::uninit(); 3 * per_slab];
+        assert_eq!(slab.alloc_bulk(&mut out), 2 * per_slab);
+        assert_eq!(slab.stats().failed, 1);
+        assert!(slab.alloc().is_none());
+    }
+
+    #[test]
+    fn test_alloc_bulk_honours_fault_injection() {
+        let _pool = reset_state();
+        let mut slab = SlabAllocator::new(64).with_fault_injection(FaultInjection::AfterN(5));
+
+        let mut out = [MaybeUninit::uninit(); 8];
+        assert_eq!(slab.alloc_bulk(&mut out), 5);
+        assert_eq!(slab.stats().failed, 1);
+        assert_eq!(slab.stats().live, 5);
+    }
+
+    #[test]
+    fn test_free_bulk_returns_objects_across_slabs() {
+        let _pool = reset_state();
+        let mut slab = SlabAllocator::new(64).detect_double_free();
+        let per_slab = slab.objects_per_slab;
+
+        let mut out = vec![MaybeUninit::uninit(); 2 * per_slab + 1];
+        slab.alloc_bulk(&mut out);
+        let mut ptrs: Vec<_> = out.iter().map(|obj| unsafe { obj.assume_init() }).collect();
+
+        // Bad pointers in the batch are skipped like `free` skips them
+        let foreign = NonNull::new(0x10 as *mut u8).unwrap();
+        let misaligned = unsafe { ptrs[1].add(1) };
+        ptrs.insert(3, foreign);
+        ptrs.insert(5, misaligned);
+        ptrs.push(ptrs[0]);
+        slab.free_bulk(&ptrs);
+
+        let stats = slab.stats();
+        assert_eq!(stats.freed, 2 * per_slab + 1);
+        assert_eq!(stats.live, 0);
+        assert_eq!(slab.live_objects().count(), 0);
+
+        // Every object comes back out again
+        let mut again = vec![MaybeUninit::uninit(); 2 * per_slab + 1];
+        assert_eq!(slab.alloc_bulk(&mut again), again.len());
+        assert_eq!(slab.stats().live, again.len());
+    }
+
+    #[test]
+    fn test_free_bulk_moves_full_slab_to_partial() {
+        let _pool = reset_state();
+        let mut slab = SlabAllocator::new(64);
+        let per_slab = slab.objects_per_slab;
+
+        let mut out = vec![MaybeUninit::uninit(); per_slab];
+        slab.alloc_bulk(&mut out);
+        let ptrs: Vec<_> = out.iter().map(|obj| unsafe { obj.assume_init() }).collect();
+
+        slab.free_bulk(&ptrs[..3]);
+        let page = slab.pages().next().unwrap();
+        assert_eq!(page.state, PageState::Partial);
+        assert_eq!(page.live, per_slab - 3);
+
+        // The freed objects are handed out again before a new slab is taken
+        let reused = slab.alloc().unwrap();
+        assert!(ptrs[..3].contains(&reused));
+        assert_eq!(slab.stats().pages, 1);
+    }
+
+    #[test]
+    fn test_bulk_in_bitmap_mode() {
+        let _pool = reset_state();
+        let mut slab = SlabAllocator::new(4).track_with_bitmap();
+
+        let mut out = [MaybeUninit::uninit(); 100];
+        assert_eq!(slab.alloc_bulk(&mut out), 100);
+        let ptrs: Vec<_> = out.iter().map(|obj| unsafe { obj.assume_init() }).collect();
+        for pair in ptrs.windows(2) {
+            assert_eq!(pair[1].as_ptr() as usize - pair[0].as_ptr() as usize, 4);
+        }
+
+        slab.free_bulk(&ptrs[10..20]);
+        assert_eq!(slab.is_allocated(ptrs[10]), Some(false));
+        assert_eq!(slab.is_allocated(ptrs[20]), Some(true));
+        assert_eq!(slab.alloc(), Some(ptrs[10]));
+    }
+
+    #[test]
+    fn test_alloc_zeroed_clears_reused_objects() {
+        let _pool = reset_state();
+        let mut slab = SlabAllocator::new(64);
+
+        let ptr = slab.alloc().unwrap();
+        unsafe { ptr.write_bytes(0xa5, 64) };
+        slab.free(ptr);
+
+        // The free list link and the old contents are both gone
+        let zeroed = slab.alloc_zeroed().unwrap();
+        assert_eq!(zeroed, ptr);
+        let bytes = unsafe { core::slice::from_raw_parts(zeroed.as_ptr(), 64) };
+        assert!(bytes.iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn test_fresh_pages_are_not_cleared_twice() {
+        let _pool = reset_state();
+        let mut slab = SlabAllocator::new(64);
+
+        // Straight from the zeroed static pool, only the link needs clearing
+        let first = slab.alloc_zeroed().unwrap();
+        let second = slab.alloc_zeroed().unwrap();
+        let page = slab.partial;
+        assert_eq!(unsafe { (*page).pristine_from }, 2);
+        for ptr in [first, second] {
+            let bytes = unsafe { core::slice::from_raw_parts(ptr.as_ptr(), 64) };
+            assert!(bytes.iter().all(|&byte| byte == 0));
+        }
+
+        // A plain alloc hands objects out as-is, but they're no longer pristine
+        slab.alloc().unwrap();
+        assert_eq!(unsafe { (*page).pristine_from }, 3);
+
+        // Region memory isn't known to be zeroed
+        let mut region = SlabAllocator::new(64).in_region(test_region(1));
+        region.alloc_zeroed().unwrap();
+        assert_eq!(
+            unsafe { (*region.partial).pristine_from },
+            region.objects_per_slab
+        );
+    }
+
+    #[test]
+    fn test_zero_on_alloc_covers_every_path() {
+        let _pool = reset_state();
+        let mut slab = SlabAllocator::new(48).zero_on_alloc();
+
+        let mut out = [MaybeUninit::uninit(); 8];
+        assert_eq!(slab.alloc_bulk(&mut out), 8);
+        let ptrs: Vec<_> = out.iter().map(|obj| unsafe { obj.assume_init() }).collect();
+        for &ptr in &ptrs {
+            unsafe { ptr.write_bytes(0xa5, 48) };
+        }
+        slab.free_bulk(&ptrs[..4]);
+        slab.free(ptrs[4]);
+
+        slab.alloc_bulk(&mut out[..4]);
+        let mut again: Vec<_> = out[..4]
+            .iter()
+            .map(|obj| unsafe { obj.assume_init() })
+            .collect();
+        again.push(slab.alloc().unwrap());
+        for ptr in again {
+            assert!(ptrs[..5].contains(&ptr));
+            let bytes = unsafe { core::slice::from_raw_parts(ptr.as_ptr(), 48) };
+            assert!(bytes.iter().all(|&byte| byte == 0));
+        }
+    }
+
+    #[test]
+    fn test_partially_used_page_is_kept() {
+        let _pool = reset_state();
+        let mut slab = SlabAllocator::new(64);
+
+        let a = slab.alloc().unwrap();
+        let b = slab.alloc().unwrap();
+        slab.free(a);
+        assert!(!slab.partial.is_null());
+
+        // b is still live and can be freed normally
+        assert_eq!(slab.try_free(b), Ok(()));
+        assert!(slab.partial.is_null());
+        assert!(!slab.empty.is_null());
+
+        // Freeing into an empty page can only be a double free
+        assert_eq!(slab.try_free(b), Err(FreeError::DoubleFree));
+    }
+
+    #[test]
+    fn test_partial_pages_served_first() {
+        let _pool = reset_state();
+        let mut slab = SlabAllocator::new(64);
+
+        // One full page and one page with a single object
+        let first_page: Vec<_> = (0..slab.objects_per_slab)
+            .map(|_| slab.alloc().unwrap())
+            .collect();
+        assert!(slab.partial.is_null());
+        let second = slab.alloc().unwrap();
+
+        // Freeing from the full page makes it partial again, and it is reused first
+        slab.free(first_page[3]);
+        assert!(slab.full.is_null());
+        assert_eq!(slab.alloc(), Some(first_page[3]));
+
+        // Objects from one page come out in address order
+        let next = slab.alloc().unwrap();
+        assert_eq!(
+            next.as_ptr() as usize,
+            second.as_ptr() as usize + slab.object_size
+        );
+    }
+
+    #[test]
+    fn test_region_backed_cache() {
+        let _pool = reset_state();
+
+        // Room for three pages wherever the buffer lands
+        let region = Vec::leak(vec![0u8; 4 * PAGE_SIZE]);
+        let (start, end) = (
+            region.as_ptr() as usize,
+            region.as_ptr() as usize + region.len(),
+        );
+        let mut slab = SlabAllocator::new(64).in_region(region);
+
+        let mut count = 0;
+        while let Some(ptr) = slab.alloc() {
+            let addr = ptr.as_ptr() as usize;
+            assert!(addr >= start && addr < end);
+            count += 1;
+        }
+        assert!(count >= 3 * slab.objects_per_slab);
+
+        // The shared pool was left alone
+        let mut shared = SlabAllocator::new(64);
+        for _ in 0..MAX_PAGES * shared.objects_per_slab {
+            assert!(shared.alloc().is_some());
+        }
+    }
+
+    // Hands out 8 KiB pages from a leaked buffer and counts releases
+    struct BigPages {
+        base: *mut u8,
+        next: usize,
+        count: usize,
+        released: usize,
+    }
+
+    impl BigPages {
+        const SIZE: usize = 2 * PAGE_SIZE;
+
+        fn new(count: usize) -> Self {
+            let buffer = Vec::leak(vec![0u8; (count + 1) * Self::SIZE]);
+            let offset = buffer.as_ptr().align_offset(Self::SIZE);
+            Self {
+                base: buffer[offset..].as_mut_ptr(),
+                next: 0,
+                count,
+                released: 0,
+            }
+        }
+    }
+
+    impl PageProvider for BigPages {
+        fn page_size(&self) -> usize {
+            Self::SIZE
+        }
+
+        fn acquire_page(&mut self) -> Option<NonNull<u8>> {
+            if self.next == self.count {
+                return None;
+            }
+            self.next += 1;
+            NonNull::new(self.base.wrapping_add((self.next - 1) * Self::SIZE))
+        }
+
+        unsafe fn release_page(&mut self, _page: NonNull<u8>) {
+            self.released += 1;
+        }
+    }
+
+    #[test]
+    fn test_custom_page_provider() {
+        let mut slab = SlabAllocator::new(64).with_provider(BigPages::new(3));
+        assert!(slab.objects_per_slab > SlabAllocator::new(64).objects_per_slab);
+
+        let ptrs: Vec<_> = core::iter::from_fn(|| slab.alloc()).collect();
+        assert_eq!(ptrs.len(), 3 * slab.objects_per_slab);
+        assert_eq!(slab.try_free(ptrs[0]), Ok(()));
+
+        // Once more than MAX_EMPTY_PAGES pages empty out, they go back to the provider
+        for ptr in &ptrs[1..] {
+            slab.free(*ptr);
+        }
+        assert_eq!(slab.provider.released, 3 - MAX_EMPTY_PAGES);
+    }
+
+    #[test]
+    fn test_mmap_pool_grows_past_static_pool() {
+        let mut slab = SlabAllocator::new(512).with_provider(MmapPool::new());
+
+        // Well beyond the 16 static pages, and across several mappings
+        let count = 4 * MAX_PAGES * slab.objects_per_slab;
+        let ptrs: Vec<_> = (0..count).map(|_| slab.alloc().unwrap()).collect();
+        for (i, ptr) in ptrs.iter().enumerate() {
+            unsafe { ptr.as_ptr().write_bytes(i as u8, 512) };
+        }
+
+        // Pages beyond MAX_EMPTY_PAGES are unmapped as they empty out
+        for ptr in ptrs {
+            assert_eq!(slab.try_free(ptr), Ok(()));
+        }
+        assert_eq!(slab.empty_pages, MAX_EMPTY_PAGES);
+        assert!(slab.alloc().is_some());
+    }
+
+    #[test]
+    fn test_slab_order_minimizes_waste() {
+        // Small objects fit fine in a single page
+        assert_eq!(SlabAllocator::new(64).slab_pages, 1);
+
+        // A page can't hold a 4 KiB object next to its header
+        let slab = SlabAllocator::new(4096);
+        assert_eq!(slab.slab_pages, 1 << MAX_ORDER);
+        assert_eq!(slab.objects_per_slab, 7);
+        assert!(slab.waste((slab.data_offset, 7, 0), MAX_ORDER) * 8 <= slab.slab_size());
+
+        // Nothing under the threshold, so the least wasteful order wins
+        let slab = SlabAllocator::new(3000);
+        assert_eq!(slab.slab_pages, 4);
+        assert_eq!(slab.objects_per_slab, 5);
+    }
+
+    #[test]
+    fn test_large_objects() {
+        let _pool = reset_state();
+
+        for size in [4096, 8192] {
+            let mut slab = SlabAllocator::new(size);
+            let ptrs: Vec<_> = (0..slab.objects_per_slab + 1)
+                .map(|_| slab.alloc().unwrap())
+                .collect();
+            for ptr in &ptrs {
+                unsafe { ptr.as_ptr().write_bytes(0xab, size) };
+            }
+            for ptr in ptrs {
+                assert_eq!(slab.try_free(ptr), Ok(()));
+            }
+
+            // Both slabs are back, one cached and one in the pool until the
+            // cache is dropped
+            assert_eq!(slab.empty_pages, MAX_EMPTY_PAGES);
+            drop(slab);
+            assert_eq!(StaticPool.stats().used_pages, 0);
+        }
+    }
+
+    #[test]
+    fn test_single_pages_given_back_serve_multi_page_slabs() {
+        let _pool = reset_state();
+
+        // Drain the pool one page at a time, then give every page back
+        let mut small = SlabAllocator::new(64);
+        let ptrs: Vec<_> = core::iter::from_fn(|| small.alloc()).collect();
+        assert_eq!(small.stats().pages, MAX_PAGES);
+        for ptr in ptrs {
+            small.free(ptr);
+        }
+
+        // The pages merged back into runs long enough for 8-page slabs
+        let mut large = SlabAllocator::new(PAGE_SIZE);
+        assert_eq!(large.slab_pages, 8);
+        let ptr = large.try_alloc().unwrap();
+        large.free(ptr);
+        drop(large);
+
+        drop(small);
+        assert_eq!(StaticPool.stats().free_pages, MAX_PAGES);
+        let mut large = SlabAllocator::new(PAGE_SIZE);
+        let ptrs: Vec<_> = (0..2 * large.objects_per_slab)
+            .map(|_| large.try_alloc().unwrap())
+            .collect();
+        for ptr in ptrs {
+            large.free(ptr);
+        }
+    }
+
+    #[test]
+    fn test_object_too_large_for_any_slab() {
+        let _pool = reset_state();
+        let mut slab = SlabAllocator::new((PAGE_SIZE << MAX_ORDER) + 1);
+
+        assert_eq!(slab.objects_per_slab, 0);
+        assert!(slab.alloc().is_none());
+    }
+
+    const CONSTRUCTED: u64 = 0x5eed_cafe_f00d_d00d;
+
+    fn construct(obj: NonNull<u8>) {
+        unsafe { obj.cast::<[u64; 2]>().write([CONSTRUCTED; 2]) };
+    }
+
+    #[test]
+    fn test_ctor_state_survives_free_list() {
+        let _pool = reset_state();
+        let mut slab = SlabAllocator::new(16).with_ctor(construct);
+
+        // The link sits after the 16 object bytes
+        assert_eq!(slab.link_offset, 16);
+        assert_eq!(slab.object_size, 24);
+
+        let objs: Vec<_> = (0..slab.objects_per_slab)
+            .map(|_| slab.alloc().unwrap())
+            .collect();
+        for obj in &objs {
+            assert_eq!(unsafe { obj.cast::<[u64; 2]>().read() }, [CONSTRUCTED; 2]);
+        }
+
+        // Freed objects come back still constructed
+        slab.free(objs[0]);
+        slab.free(objs[1]);
+        for _ in 0..2 {
+            let obj = slab.alloc().unwrap();
+            assert_eq!(unsafe { obj.cast::<[u64; 2]>().read() }, [CONSTRUCTED; 2]);
+        }
+    }
+
+    static DESTROYED: AtomicUsize = AtomicUsize::new(0);
+
+    fn destroy(obj: NonNull<u8>) {
+        assert_eq!(unsafe { obj.cast::<[u64; 2]>().read() }, [CONSTRUCTED; 2]);
+        DESTROYED.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[test]
+    fn test_dtor_runs_when_slab_is_released() {
+        let _pool = reset_state();
+        let mut slab = SlabAllocator::new(16)
+            .with_ctor(construct)
+            .with_dtor(destroy);
+
+        // Two slabs, so one is released once both empty out
+        let objs: Vec<_> = (0..slab.objects_per_slab + 1)
+            .map(|_| slab.alloc().unwrap())
+            .collect();
+        assert_eq!(DESTROYED.load(Ordering::Relaxed), 0);
+        for obj in objs {
+            slab.free(obj);
+        }
+        assert_eq!(DESTROYED.load(Ordering::Relaxed), slab.objects_per_slab);
+    }
+
+    #[test]
+    fn test_coloring_rotates_data_start() {
+        let _pool = reset_state();
+        let layout = Layout::from_size_align(200, 64).unwrap();
+        let mut slab = SlabAllocator::with_layout(layout).with_coloring(64);
+
+        // 15 objects of 256 bytes after a 64 byte header leave 192 bytes, so
+        // four colors fit before wrapping around
+        assert_eq!(slab.tail(), 192);
+        let mut firsts = Vec::new();
+        for _ in 0..5 {
+            let ptrs: Vec<_> = (0..slab.objects_per_slab)
+                .map(|_| slab.alloc().unwrap())
+                .collect();
+            let first = ptrs[0].as_ptr() as usize;
+            let page = first & !(PAGE_SIZE - 1);
+            assert!(
+                ptrs.iter()
+                    .all(|p| (p.as_ptr() as usize).is_multiple_of(64))
+            );
+            assert!(
+                ptrs.iter()
+                    .all(|p| p.as_ptr() as usize + 200 <= page + PAGE_SIZE)
+            );
+            firsts.push((first - page, ptrs));
+        }
+        let offsets: Vec<_> = firsts.iter().map(|(offset, _)| *offset).collect();
+        assert_eq!(offsets, [64, 128, 192, 256, 64]);
+
+        // Frees are checked against each slab's own data start
+        let (_, ptrs) = &firsts[1];
+        let interior = unsafe { NonNull::new_unchecked(ptrs[0].as_ptr().sub(64)) };
+        assert_eq!(slab.try_free(interior), Err(FreeError::Misaligned));
+        for &ptr in ptrs {
+            assert_eq!(slab.try_free(ptr), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_bitmap_tracking_serves_tiny_objects() {
+        let _pool = reset_state();
+        let mut slab = SlabAllocator::with_layout(Layout::new::<u16>()).track_with_bitmap();
+        assert_eq!(slab.object_size(), 2);
+        assert_eq!(slab.align(), 2);
+
+        // Objects are packed back to back, in address order
+        let ptrs: Vec<_> = (0..slab.objects_per_slab)
+            .map(|_| slab.alloc().unwrap())
+            .collect();
+        assert!(slab.objects_per_slab > PAGE_SIZE / 4);
+        for pair in ptrs.windows(2) {
+            assert_eq!(pair[1].as_ptr() as usize - pair[0].as_ptr() as usize, 2);
+        }
+
+        // Writing every object leaves the bookkeeping intact
+        for (i, ptr) in ptrs.iter().enumerate() {
+            unsafe { ptr.cast::<u16>().write(i as u16) };
+        }
+        assert_eq!(slab.try_free(ptrs[7]), Ok(()));
+        assert_eq!(slab.try_free(ptrs[7]), Err(FreeError::DoubleFree));
+        assert_eq!(slab.alloc(), Some(ptrs[7]));
+        assert_eq!(unsafe { ptrs[8].cast::<u16>().read() }, 8);
+    }
+
+    #[test]
+    fn test_bitmap_tracking_keeps_ctor_state_in_place() {
+        let _pool = reset_state();
+        let mut slab = SlabAllocator::new(8)
+            .with_ctor(|obj| unsafe { obj.cast::<u64>().write(0xfeed) })
+            .track_with_bitmap();
+
+        // No link after the object, so the stride is just the object
+        assert_eq!(slab.object_size(), 8);
+        let ptr = slab.alloc().unwrap();
+        slab.free(ptr);
+        assert_eq!(unsafe { ptr.cast::<u64>().read() }, 0xfeed);
+    }
+
+    #[test]
+    fn test_allocated_state_and_live_objects() {
+        let _pool = reset_state();
+
+        for bitmap in [false, true] {
+            SlabAllocator::reset_pool();
+            let mut slab = SlabAllocator::new(48);
+            if bitmap {
+                slab = slab.track_with_bitmap();
+            }
+
+            let ptrs: Vec<_> = (0..slab.objects_per_slab + 2)
+                .map(|_| slab.alloc().unwrap())
+                .collect();
+            slab.free(ptrs[1]);
+            slab.free(ptrs[ptrs.len() - 1]);
+
+            assert_eq!(slab.is_allocated(ptrs[0]), Some(true));
+            assert_eq!(slab.is_allocated(ptrs[1]), Some(false));
+            let interior = unsafe { NonNull::new_unchecked(ptrs[0].as_ptr().add(1)) };
+            assert_eq!(slab.is_allocated(interior), None);
+
+            let mut live: Vec<_> = slab.live_objects().collect();
+            live.sort();
+            let mut expected: Vec<_> = ptrs.clone();
+            expected.remove(ptrs.len() - 1);
+            expected.remove(1);
+            expected.sort();
+            assert_eq!(live, expected);
+        }
+    }
+
+    #[test]
+    fn test_pages_and_objects_report_occupancy() {
+        let _pool = reset_state();
+        let mut slab = SlabAllocator::new(64);
+        let per_slab = slab.objects_per_slab;
+
+        // One full slab, one partial slab and one cached empty slab
+        let ptrs: Vec<_> = (0..3 * per_slab).map(|_| slab.alloc().unwrap()).collect();
+        for &ptr in &ptrs[per_slab + 2..] {
+            slab.free(ptr);
+        }
+
+        let pages: Vec<_> = slab.pages().collect();
+        let states: Vec<_> = pages.iter().map(|page| page.state).collect();
+        assert_eq!(
+            states,
+            [PageState::Partial, PageState::Full, PageState::Empty]
+        );
+        assert_eq!(pages[0].live, 2);
+        assert_eq!(pages[0].free(), per_slab - 2);
+        assert_eq!(pages[1].live, per_slab);
+        assert_eq!(pages[2].live, 0);
+        assert!(pages.iter().all(|page| page.pages == 1));
+        assert_eq!(
+            pages[1].addr.as_ptr() as usize,
+            ptrs[0].as_ptr() as usize & !(PAGE_SIZE - 1)
+        );
+
+        // Slots come out page by page, in address order within a page
+        let objects: Vec<_> = slab.objects().collect();
+        assert_eq!(objects.len(), 3 * per_slab);
+        let partial = &objects[..per_slab];
+        assert_eq!(
+            partial[0],
+            ObjectInfo {
+                ptr: ptrs[per_slab],
+                allocated: true
+            }
+        );
+        assert!(partial[1].allocated && !partial[2].allocated);
+        assert!(objects[per_slab..2 * per_slab].iter().all(|o| o.allocated));
+        assert!(objects[2 * per_slab..].iter().all(|o| !o.allocated));
+    }
+
+    #[test]
+    fn test_fault_injection_every_nth() {
+        let _pool = reset_state();
+        let mut slab = SlabAllocator::new(32).with_fault_injection(FaultInjection::EveryNth(3));
+
+        let results: Vec<_> = (0..7).map(|_| slab.alloc().is_some()).collect();
+        assert_eq!(results, [true, true, false, true, true, false, true]);
+        assert_eq!(slab.stats().failed, 2);
+    }
+
+    #[test]
+    fn test_fault_injection_after_n() {
+        let _pool = reset_state();
+        let mut slab = SlabAllocator::new(32);
+        slab.set_fault_injection(Some(FaultInjection::AfterN(2)));
+
+        assert!(slab.alloc().is_some());
+        let ptr = slab.alloc().unwrap();
+        assert!(slab.alloc().is_none());
+        assert!(slab.alloc().is_none());
+
+        // Freeing still works, and switching injection off restores allocation
+        assert_eq!(slab.try_free(ptr), Ok(()));
+        slab.set_fault_injection(None);
+        assert_eq!(slab.alloc(), Some(ptr));
+    }
+
+    #[test]
+    fn test_fault_injection_random_is_reproducible() {
+        // Allocate 200 times, free what succeeded and report which failed
+        fn run(slab: &mut SlabAllocator) -> Vec<bool> {
+            let results = (0..200).map(|_| slab.alloc()).collect::<Vec<_>>();
+            let pattern = results.iter().map(Option::is_none).collect();
+            for ptr in results.into_iter().flatten() {
+                slab.free(ptr);
+            }
+            pattern
+        }
+
+        let _pool = reset_state();
+        let mode = FaultInjection::Random {
+            seed: 42,
+            percent: 25,
+        };
+        let mut slab = SlabAllocator::new(32).with_fault_injection(mode);
+        let first = run(&mut slab);
+        let failures = first.iter().filter(|&&failed| failed).count();
+        assert!((25..75).contains(&failures));
+
+        // The same seed fails the same allocations
+        slab.set_fault_injection(Some(mode));
+        assert_eq!(run(&mut slab), first);
+
+        // Never and always are exact
+        slab.set_fault_injection(Some(FaultInjection::Random {
+            seed: 7,
+            percent: 0,
+        }));
+        assert!(run(&mut slab).iter().all(|&failed| !failed));
+        slab.set_fault_injection(Some(FaultInjection::Random {
+            seed: 7,
+            percent: 100,
+        }));
+        assert!(run(&mut slab).iter().all(|&failed| failed));
+    }
+
+    #[test]
+    fn test_stats_track_objects_and_pages() {
+        let _pool = reset_state();
+        let mut slab = SlabAllocator::new(100).detect_double_free();
+        let per_slab = slab.objects_per_slab;
+        assert_eq!(slab.stats(), SlabStats::default());
+
+        let ptrs: Vec<_> = (0..per_slab + 1).map(|_| slab.alloc().unwrap()).collect();
+        for &ptr in &ptrs[..3] {
+            slab.free(ptr);
+        }
+        // Rejected frees don't count
+        slab.free(ptrs[0]);
+
+        let stats = slab.stats();
+        assert_eq!(stats.allocated, per_slab + 1);
+        assert_eq!(stats.freed, 3);
+        assert_eq!(stats.live, per_slab - 2);
+        assert_eq!(stats.high_water, per_slab + 1);
+        assert_eq!(stats.free, per_slab + 2);
+        assert_eq!(stats.pages, 2);
+        assert_eq!(
+            stats.wasted_bytes,
+            2 * (PAGE_SIZE - per_slab * slab.object_size())
+        );
+    }
+
+    #[test]
+    fn test_failed_allocations_are_counted() {
+        let _pool = reset_state();
+        let mut slab = SlabAllocator::new(PAGE_SIZE * 2);
+
+        let served = core::iter::from_fn(|| slab.alloc()).count();
+        assert!(slab.alloc().is_none());
+
+        let stats = slab.stats();
+        assert_eq!(stats.allocated, served);
+        assert_eq!(stats.failed, 2);
+        assert_eq!(stats.pages, MAX_PAGES);
+        assert_eq!(StaticPool.stats().free_pages, 0);
+    }
+
+    #[test]
+    fn test_try_alloc_reports_each_error() {
+        let _pool = reset_state();
+
+        let mut huge = SlabAllocator::new((PAGE_SIZE << MAX_ORDER) + 1);
+        assert_eq!(huge.try_alloc(), Err(AllocError::ObjectTooLarge));
+
+        let mut slab = SlabAllocator::new(64);
+        slab.set_fault_injection(Some(FaultInjection::AfterN(1)));
+        let ptr = slab.try_alloc().unwrap();
+        assert_eq!(slab.try_alloc(), Err(AllocError::InjectedFault));
+
+        // With injection off, the cache fails only once the pool runs dry
+        slab.set_fault_injection(None);
+        let served = core::iter::from_fn(|| slab.try_alloc().ok()).count();
+        assert_eq!(slab.try_alloc(), Err(AllocError::PoolExhausted));
+        assert_eq!(StaticPool.stats().free_pages, 0);
+
+        // Each error counts as a failed allocation, just as with alloc
+        assert_eq!(huge.stats().failed, 1);
+        assert_eq!(slab.stats().failed, 3);
+        assert_eq!(slab.stats().allocated, served + 1);
+        slab.free(ptr);
+        assert_eq!(slab.try_alloc(), Ok(ptr));
+    }
+
+    #[test]
+    fn test_alloc_after_free() {
+        let _pool = reset_state();
         let mut slab = SlabAllocator::new(64);
 
         let mut ptrs = Vec::new();
@@ -269,7 +2204,7 @@ mod tests {
 
     #[test]
     fn test_object_size_alignment() {
-        reset_state();
+        let _pool = reset_state();
         // Test that object size is properly aligned
         let slab = SlabAllocator::new(13); // Not aligned to pointer size
 
@@ -281,13 +2216,47 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_with_layout_alignment() {
+        let _pool = reset_state();
+
+        for align in [16, 32, 64, 128] {
+            let layout = Layout::from_size_align(24, align).unwrap();
+            let mut slab = SlabAllocator::with_layout(layout);
+            assert_eq!(slab.object_size() % align, 0);
+
+            // Span more than one page so every page's data start is checked
+            for _ in 0..=slab.objects_per_slab {
+                let ptr = slab.alloc().unwrap();
+                assert_eq!(ptr.as_ptr() as usize % align, 0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_alignment_beyond_page_size_is_refused() {
+        let _pool = reset_state();
+        let mut slab =
+            SlabAllocator::with_layout(Layout::from_size_align(8, 2 * PAGE_SIZE).unwrap());
+
+        assert_eq!(slab.objects_per_slab, 0);
+        assert_eq!(slab.try_alloc(), Err(AllocError::ObjectTooLarge));
+        assert_eq!(StaticPool.stats().used_pages, 0);
+
+        // Page alignment itself still works, with the data on its own page
+        let mut slab = SlabAllocator::with_layout(Layout::from_size_align(8, PAGE_SIZE).unwrap());
+        let ptr = slab.alloc().unwrap();
+        assert!((ptr.as_ptr() as usize).is_multiple_of(PAGE_SIZE));
+        slab.free(ptr);
+    }
+
     #[test]
     fn test_multiple_pages() {
-        reset_state();
+        let _pool = reset_state();
         let mut slab = SlabAllocator::new(64);
 
         // Allocate enough objects to require multiple pages
-        let objects_per_page = slab.objects_per_page;
+        let objects_per_page = slab.objects_per_slab;
         let mut ptrs = Vec::new();
 
         // Allocate objects from first page
@@ -0,0 +1,513 @@
+use core::ptr;
+use core::ptr::NonNull;
+use core::ptr::addr_of_mut;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::stats::PoolStats;
+use crate::sync::SpinLock;
+use crate::sys::{mmap_anonymous, munmap};
+use crate::{MAX_PAGES, PAGE_SIZE};
+
+// Page-aligned storage, so offsets inside a page are also address alignments
+#[repr(C, align(4096))]
+struct PageAligned<T>(T);
+
+// Memory pool for pages
+static mut PAGE_POOL: PageAligned<[u8; MAX_PAGES * PAGE_SIZE]> =
+    PageAligned([0; MAX_PAGES * PAGE_SIZE]);
+
+// Pool over PAGE_POOL, shared by every cache using StaticPool
+static STATIC_POOL: PagePool = PagePool {
+    base: addr_of_mut!(PAGE_POOL) as *mut u8,
+    size: MAX_PAGES * PAGE_SIZE,
+    used: AtomicUsize::new(0),
+    touched: AtomicUsize::new(0),
+    zeroed: true,
+    free_pages: SpinLock::new(ptr::null_mut()),
+};
+
+/// Source of the pages a `SlabAllocator` carves objects from.
+///
+/// Pages must be `page_size()` bytes long and aligned to `page_size()`,
+/// which must be a power of two, so object alignment can be worked out
+/// once per cache.
+pub trait PageProvider {
+    /// Size and alignment of every page handed out.
+    fn page_size(&self) -> usize;
+
+    /// Hand out a page, or `None` when no memory is left.
+    fn acquire_page(&mut self) -> Option<NonNull<u8>>;
+
+    /// Take back a page that is no longer used.
+    ///
+    /// # Safety
+    ///
+    /// `page` must come from `acquire_page` on this provider and not be used afterwards.
+    unsafe fn release_page(&mut self, page: NonNull<u8>);
+
+    /// Hand out `count` contiguous pages for a multi-page slab. Providers
+    /// that only deal in single pages keep this default, which fails for
+    /// `count > 1`.
+    fn acquire_pages(&mut self, count: usize) -> Option<NonNull<u8>> {
+        if count == 1 {
+            self.acquire_page()
+        } else {
+            None
+        }
+    }
+
+    /// Take back `count` contiguous pages that are no longer used.
+    ///
+    /// # Safety
+    ///
+    /// `pages` must come from `acquire_pages(count)` on this provider and not
+    /// be used afterwards.
+    unsafe fn release_pages(&mut self, pages: NonNull<u8>, count: usize) {
+        for i in 0..count {
+            // SAFETY: The run is count pages long, so every page is one we handed out.
+            unsafe { self.release_page(pages.add(i * self.page_size())) };
+        }
+    }
+
+    /// Hand out `count` contiguous pages like `acquire_pages`, also saying
+    /// whether they are known to hold nothing but zero bytes, so caches that
+    /// hand out zeroed objects can skip clearing them. Providers that can't
+    /// tell keep this default, which always says `false`.
+    fn acquire_zeroed_pages(&mut self, count: usize) -> Option<(NonNull<u8>, bool)> {
+        self.acquire_pages(count).map(|pages| (pages, false))
+    }
+}
+
+/// Provider over the built-in static pool of `MAX_PAGES` pages, shared by
+/// every cache that uses it.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StaticPool;
+
+impl StaticPool {
+    /// Page usage of the shared pool, across every cache using it.
+    pub fn stats(&self) -> PoolStats {
+        PagePool::shared().stats()
+    }
+}
+
+impl PageProvider for StaticPool {
+    fn page_size(&self) -> usize {
+        PAGE_SIZE
+    }
+
+    fn acquire_page(&mut self) -> Option<NonNull<u8>> {
+        self.acquire_pages(1)
+    }
+
+    unsafe fn release_page(&mut self, page: NonNull<u8>) {
+        // SAFETY: The caller hands back a page it got from the shared pool.
+        unsafe { PagePool::shared().give_back_pages(page, 1) }
+    }
+
+    fn acquire_pages(&mut self, count: usize) -> Option<NonNull<u8>> {
+        PagePool::shared().take_pages(count).map(|(pages, _)| pages)
+    }
+
+    unsafe fn release_pages(&mut self, pages: NonNull<u8>, count: usize) {
+        // SAFETY: The caller hands back a run it got from the shared pool.
+        unsafe { PagePool::shared().give_back_pages(pages, count) }
+    }
+
+    fn acquire_zeroed_pages(&mut self, count: usize) -> Option<(NonNull<u8>, bool)> {
+        PagePool::shared().take_pages(count)
+    }
+}
+
+// Free list node stored at the start of a run of pages returned to the pool
+struct FreePage {
+    next: *mut FreePage,
+    pages: usize,
+}
+
+/// Hands out pages from one contiguous memory region.
+///
+/// Pages are bump-allocated from the start of the region, and runs of pages
+/// given back are reused, first fit, before the bump pointer moves again.
+/// Neighbouring runs are merged, and a run reaching the bump pointer moves
+/// it back, so single pages given back can serve multi-page slabs again.
+/// The pool can be shared between threads: the bump pointer is atomic and
+/// the list of runs sits behind a spinlock.
+pub struct PagePool {
+    base: *mut u8,
+    size: usize,
+    // Bytes bump-allocated from the start of the region
+    used: AtomicUsize,
+    // Highest `used` ever reached; pages past it were never handed out
+    touched: AtomicUsize,
+    // Whether the region started out zeroed, so untouched pages still are
+    zeroed: bool,
+    // Runs of pages given back by caches, sorted by address and never
+    // touching each other, reused before bumping `used`
+    free_pages: SpinLock<*mut FreePage>,
+}
+
+// SAFETY: base and size never change, the rest is atomic or behind the lock,
+// and the pool owns the pages on its free list.
+unsafe impl Send for PagePool {}
+unsafe impl Sync for PagePool {}
+
+impl PagePool {
+    /// Manage the pages of `region`, for example a linker section or a buffer
+    /// handed over by firmware. The start is rounded up to a page boundary and
+    /// a partial page at the end is left unused.
+    pub fn from_region(region: &'static mut [u8]) -> Self {
+        let start = region.as_mut_ptr() as usize;
+        let end = start + region.len();
+        let base = (start + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+        let size = end.saturating_sub(base) & !(PAGE_SIZE - 1);
+
+        Self {
+            base: region.as_mut_ptr().wrapping_add(base - start),
+            size,
+            used: AtomicUsize::new(0),
+            touched: AtomicUsize::new(0),
+            zeroed: false,
+            free_pages: SpinLock::new(ptr::null_mut()),
+        }
+    }
+
+    // The pool over PAGE_POOL
+    pub(crate) fn shared() -> &'static PagePool {
+        &STATIC_POOL
+    }
+
+    /// Page usage of the pool.
+    pub fn stats(&self) -> PoolStats {
+        let free_pages = self.free_pages.lock();
+        let bumped = self.used.load(Ordering::Acquire) / PAGE_SIZE;
+
+        let mut returned = 0;
+        let mut run = *free_pages;
+        while !run.is_null() {
+            // SAFETY: Runs on the free list start with a FreePage, and we hold the lock.
+            unsafe {
+                returned += (*run).pages;
+                run = (*run).next;
+            }
+        }
+
+        let total_pages = self.size / PAGE_SIZE;
+        PoolStats {
+            total_pages,
+            used_pages: bumped - returned,
+            free_pages: total_pages - bumped + returned,
+        }
+    }
+
+    // Forget every page handed out, clearing them again if the region
+    // started out zeroed
+    pub(crate) fn reset(&self) {
+        let mut free_pages = self.free_pages.lock();
+        *free_pages = ptr::null_mut();
+        if self.zeroed {
+            let touched = self.touched.swap(0, Ordering::AcqRel);
+            // SAFETY: Every page up to touched lies in the region, and none is in use.
+            unsafe { ptr::write_bytes(self.base, 0, touched) };
+        }
+        self.used.store(0, Ordering::Release);
+    }
+
+    // Hand out a run of count pages, through a shared reference, and whether
+    // it still holds the region's initial zeroes
+    fn take_pages(&self, count: usize) -> Option<(NonNull<u8>, bool)> {
+        let len = count * PAGE_SIZE;
+
+        // Reuse the first run caches gave back that is long enough
+        {
+            let mut free_pages = self.free_pages.lock();
+            let mut link: *mut *mut FreePage = &mut *free_pages;
+            // SAFETY: Runs on the free list start with a FreePage written by
+            // give_back_pages, and the lock keeps other threads off the list.
+            unsafe {
+                while !(*link).is_null() {
+                    let run = *link;
+                    if (*run).pages == count {
+                        *link = (*run).next;
+                        return NonNull::new(run as *mut u8).map(|run| (run, false));
+                    }
+                    if (*run).pages > count {
+                        // Hand out the tail so the run's header stays put
+                        (*run).pages -= count;
+                        let tail = (run as *mut u8).add((*run).pages * PAGE_SIZE);
+                        return NonNull::new(tail).map(|tail| (tail, false));
+                    }
+                    link = &raw mut (*run).next;
+                }
+            }
+        }
+
+        // Bump the pointer if there is space for more pages
+        let used = self
+            .used
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+                (used + len <= self.size).then_some(used + len)
+            })
+            .ok()?;
+
+        // Pages the bump pointer was rewound over have been written to
+        let touched = self.touched.fetch_max(used + len, Ordering::AcqRel);
+        let zeroed = self.zeroed && used >= touched;
+
+        // SAFETY: used + len is within bounds, so add stays within the region.
+        NonNull::new(unsafe { self.base.add(used) }).map(|pages| (pages, zeroed))
+    }
+
+    // Take back a run of count pages, through a shared reference
+    // SAFETY: pages must come from take_pages(count) and not be used afterwards.
+    unsafe fn give_back_pages(&self, pages: NonNull<u8>, count: usize) {
+        let mut start = pages.as_ptr() as usize;
+        let mut end = start + count * PAGE_SIZE;
+
+        let mut free_pages = self.free_pages.lock();
+        let mut link: *mut *mut FreePage = &mut *free_pages;
+        // SAFETY: Runs on the free list start with a FreePage, the lock keeps
+        // other threads off the list, and the run given back is ours again.
+        unsafe {
+            // The list is sorted by address, so find the run's place in it,
+            // absorbing the run that ends where it starts
+            while !(*link).is_null() && ((*link) as usize) < start {
+                let run = *link;
+                if run as usize + (*run).pages * PAGE_SIZE == start {
+                    start = run as usize;
+                    *link = (*run).next;
+                } else {
+                    link = &raw mut (*run).next;
+                }
+            }
+
+            // And the run that starts where it ends
+            let next = *link;
+            if next as usize == end {
+                end += (*next).pages * PAGE_SIZE;
+                *link = (*next).next;
+            }
+
+            // The run ends at the bump pointer, so just move it back. If
+            // another thread moved it in the meantime, the run stays listed.
+            let (offset, top) = (start - self.base as usize, end - self.base as usize);
+            if self
+                .used
+                .compare_exchange(top, offset, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+            {
+                return;
+            }
+
+            let run = start as *mut FreePage;
+            (*run).next = *link;
+            (*run).pages = (end - start) / PAGE_SIZE;
+            *link = run;
+        }
+    }
+}
+
+impl PageProvider for PagePool {
+    fn page_size(&self) -> usize {
+        PAGE_SIZE
+    }
+
+    fn acquire_page(&mut self) -> Option<NonNull<u8>> {
+        self.acquire_pages(1)
+    }
+
+    unsafe fn release_page(&mut self, page: NonNull<u8>) {
+        unsafe { self.give_back_pages(page, 1) }
+    }
+
+    fn acquire_pages(&mut self, count: usize) -> Option<NonNull<u8>> {
+        self.take_pages(count).map(|(pages, _)| pages)
+    }
+
+    unsafe fn release_pages(&mut self, pages: NonNull<u8>, count: usize) {
+        unsafe { self.give_back_pages(pages, count) }
+    }
+
+    fn acquire_zeroed_pages(&mut self, count: usize) -> Option<(NonNull<u8>, bool)> {
+        self.take_pages(count)
+    }
+}
+
+// Pages mapped per mmap call, so growing the slab isn't one syscall per page
+const MMAP_CHUNK_PAGES: usize = 16;
+
+/// Provider that maps anonymous memory from the kernel as caches grow and
+/// unmaps pages as they are released, so it is only bounded by the address
+/// space. Assumes the kernel uses `PAGE_SIZE` pages.
+pub struct MmapPool {
+    // Next unused page of the current mapping
+    next: *mut u8,
+    remaining: usize,
+}
+
+impl MmapPool {
+    pub const fn new() -> Self {
+        Self {
+            next: ptr::null_mut(),
+            remaining: 0,
+        }
+    }
+}
+
+// SAFETY: The pool owns the rest of its current mapping outright.
+unsafe impl Send for MmapPool {}
+
+impl Default for MmapPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PageProvider for MmapPool {
+    fn page_size(&self) -> usize {
+        PAGE_SIZE
+    }
+
+    fn acquire_page(&mut self) -> Option<NonNull<u8>> {
+        self.acquire_pages(1)
+    }
+
+    unsafe fn release_page(&mut self, page: NonNull<u8>) {
+        unsafe { self.release_pages(page, 1) }
+    }
+
+    fn acquire_pages(&mut self, count: usize) -> Option<NonNull<u8>> {
+        if self.remaining < count {
+            // Map a fresh chunk, big enough for the whole slab
+            let pages = count.max(MMAP_CHUNK_PAGES);
+            let chunk = mmap_anonymous(pages * PAGE_SIZE).ok()?;
+
+            // The tail of the old chunk is too short for this slab, so give it back
+            if let Some(rest) = NonNull::new(self.next).filter(|_| self.remaining != 0) {
+                // SAFETY: Nothing was handed out from the tail of the old chunk.
+                let _ = unsafe { munmap(rest, self.remaining * PAGE_SIZE) };
+            }
+            self.next = chunk.as_ptr();
+            self.remaining = pages;
+        }
+
+        let run = self.next;
+        // SAFETY: remaining covers count pages, so the end is still in the mapping or its end.
+        self.next = unsafe { self.next.add(count * PAGE_SIZE) };
+        self.remaining -= count;
+        NonNull::new(run)
+    }
+
+    unsafe fn release_pages(&mut self, pages: NonNull<u8>, count: usize) {
+        // munmap can split a mapping, so each slab goes back on its own.
+        // Failing to unmap only leaks the pages.
+        let _ = unsafe { munmap(pages, count * PAGE_SIZE) };
+    }
+
+    fn acquire_zeroed_pages(&mut self, count: usize) -> Option<(NonNull<u8>, bool)> {
+        // Fresh anonymous mappings are zeroed, and released pages are never reused
+        self.acquire_pages(count).map(|pages| (pages, true))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_region;
+    use std::vec::Vec;
+
+    fn region(pages: usize) -> PagePool {
+        PagePool::from_region(test_region(pages))
+    }
+
+    #[test]
+    fn test_runs_are_reused_first_fit() {
+        let mut pool = region(8);
+
+        let a = pool.acquire_pages(4).unwrap();
+        let b = pool.acquire_pages(2).unwrap();
+        assert_eq!(b.as_ptr() as usize - a.as_ptr() as usize, 4 * PAGE_SIZE);
+
+        // A four page hole serves a two page run from its tail, then a single page
+        unsafe { pool.release_pages(a, 4) };
+        let c = pool.acquire_pages(2).unwrap();
+        assert_eq!(c.as_ptr() as usize, a.as_ptr() as usize + 2 * PAGE_SIZE);
+        assert_eq!(
+            pool.acquire_page().unwrap().as_ptr() as usize,
+            a.as_ptr() as usize + PAGE_SIZE
+        );
+    }
+
+    #[test]
+    fn test_run_at_the_top_rewinds_bump_pointer() {
+        let mut pool = region(4);
+
+        let a = pool.acquire_pages(2).unwrap();
+        let b = pool.acquire_pages(2).unwrap();
+        assert!(pool.acquire_page().is_none());
+
+        unsafe { pool.release_pages(b, 2) };
+        assert_eq!(pool.used.load(Ordering::Relaxed), 2 * PAGE_SIZE);
+        assert!(pool.free_pages.lock().is_null());
+
+        // The whole tail is contiguous again
+        unsafe { pool.release_pages(a, 2) };
+        assert_eq!(pool.acquire_pages(4), Some(a));
+    }
+
+    #[test]
+    fn test_only_untouched_pages_count_as_zeroed() {
+        let mut pool = PagePool {
+            zeroed: true,
+            ..region(6)
+        };
+
+        let (a, fresh) = pool.acquire_zeroed_pages(2).unwrap();
+        assert!(fresh);
+        let (b, fresh) = pool.acquire_zeroed_pages(1).unwrap();
+        assert!(fresh);
+
+        // Rewinding the bump pointer doesn't make b's page fresh again
+        unsafe { pool.release_page(b) };
+        assert_eq!(pool.acquire_zeroed_pages(1), Some((b, false)));
+
+        // Nor does reusing a returned run
+        unsafe { pool.release_pages(a, 2) };
+        assert_eq!(pool.acquire_zeroed_pages(2), Some((a, false)));
+        assert!(pool.acquire_zeroed_pages(2).unwrap().1);
+    }
+
+    #[test]
+    fn test_neighbouring_runs_merge() {
+        let mut pool = region(8);
+        let pages: Vec<_> = (0..4).map(|_| pool.acquire_pages(2).unwrap()).collect();
+
+        // Given back out of order, the first three runs still become one
+        unsafe {
+            pool.release_pages(pages[2], 2);
+            pool.release_pages(pages[0], 2);
+            pool.release_pages(pages[1], 2);
+        }
+        assert_eq!(pool.acquire_pages(6), Some(pages[0]));
+
+        // A run reaching the bump pointer takes the runs before it along
+        unsafe {
+            pool.release_pages(pages[0], 6);
+            pool.release_pages(pages[3], 2);
+        }
+        assert_eq!(pool.used.load(Ordering::Relaxed), 0);
+        assert!(pool.free_pages.lock().is_null());
+    }
+
+    #[test]
+    fn test_stats_count_returned_runs_as_free() {
+        let mut pool = region(6);
+        let a = pool.acquire_pages(2).unwrap();
+        let _b = pool.acquire_page().unwrap();
+
+        unsafe { pool.release_pages(a, 2) };
+        let stats = pool.stats();
+        assert_eq!(stats.total_pages, 6);
+        assert_eq!(stats.used_pages, 1);
+        assert_eq!(stats.free_pages, 5);
+    }
+}
@@ -0,0 +1,132 @@
+use core::alloc::Layout;
+use core::ptr::NonNull;
+
+use crate::SlabAllocator;
+
+// Power-of-two classes plus the 96 and 192 byte classes in between,
+// the same table Linux uses for kmalloc
+const CLASS_SIZES: [usize; 11] = [8, 16, 32, 64, 96, 128, 192, 256, 512, 1024, 2048];
+
+/// Largest request a size class can serve.
+pub const MAX_CLASS_SIZE: usize = CLASS_SIZES[CLASS_SIZES.len() - 1];
+
+/// kmalloc-style front end owning one slab cache per size class.
+///
+/// Each request is served by the smallest class that fits it, so callers
+/// must pass the same size to `free` that they passed to `alloc`. Objects are
+/// aligned to the largest power of two dividing their class size.
+pub struct SizeClassAllocator {
+    caches: [SlabAllocator; CLASS_SIZES.len()],
+}
+
+// Layout of a size class, naturally aligned like kmalloc objects
+const fn class_layout(size: usize) -> Layout {
+    let align = 1 << size.trailing_zeros();
+    match Layout::from_size_align(size, align) {
+        Ok(layout) => layout,
+        Err(_) => panic!("invalid size class"),
+    }
+}
+
+impl SizeClassAllocator {
+    pub const fn new() -> Self {
+        Self {
+            caches: [
+                SlabAllocator::with_layout(class_layout(CLASS_SIZES[0])),
+                SlabAllocator::with_layout(class_layout(CLASS_SIZES[1])),
+                SlabAllocator::with_layout(class_layout(CLASS_SIZES[2])),
+                SlabAllocator::with_layout(class_layout(CLASS_SIZES[3])),
+                SlabAllocator::with_layout(class_layout(CLASS_SIZES[4])),
+                SlabAllocator::with_layout(class_layout(CLASS_SIZES[5])),
+                SlabAllocator::with_layout(class_layout(CLASS_SIZES[6])),
+                SlabAllocator::with_layout(class_layout(CLASS_SIZES[7])),
+                SlabAllocator::with_layout(class_layout(CLASS_SIZES[8])),
+                SlabAllocator::with_layout(class_layout(CLASS_SIZES[9])),
+                SlabAllocator::with_layout(class_layout(CLASS_SIZES[10])),
+            ],
+        }
+    }
+
+    // Index of the smallest class that holds `size` bytes
+    fn class_index(&self, size: usize) -> Option<usize> {
+        self.caches
+            .iter()
+            .position(|cache| cache.object_size() >= size)
+    }
+
+    /// Object size of the class that serves `size`, or `None` if it is too large.
+    pub fn class_size(&self, size: usize) -> Option<usize> {
+        self.class_index(size)
+            .map(|index| self.caches[index].object_size())
+    }
+
+    /// Allocate `size` bytes, returning the object and the size of the class that served it.
+    pub fn alloc(&mut self, size: usize) -> Option<(NonNull<u8>, usize)> {
+        let index = self.class_index(size)?;
+        let cache = &mut self.caches[index];
+        let ptr = cache.alloc()?;
+        Some((ptr, cache.object_size()))
+    }
+
+    /// Free an object previously returned by `alloc(size)`.
+    pub fn free(&mut self, ptr: NonNull<u8>, size: usize) {
+        if let Some(index) = self.class_index(size) {
+            self.caches[index].free(ptr);
+        }
+    }
+}
+
+impl Default for SizeClassAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lock_pool;
+
+    #[test]
+    fn test_smallest_fitting_class_serves_request() {
+        let _pool = lock_pool();
+        let mut classes = SizeClassAllocator::new();
+
+        assert_eq!(classes.class_size(0), Some(8));
+        assert_eq!(classes.class_size(65), Some(96));
+        assert_eq!(classes.class_size(150), Some(192));
+        assert_eq!(classes.class_size(MAX_CLASS_SIZE), Some(MAX_CLASS_SIZE));
+        assert_eq!(classes.class_size(MAX_CLASS_SIZE + 1), None);
+
+        let (_, class) = classes.alloc(100).unwrap();
+        assert_eq!(class, 128);
+        assert!(classes.alloc(MAX_CLASS_SIZE + 1).is_none());
+    }
+
+    #[test]
+    fn test_free_returns_object_to_its_class() {
+        let _pool = lock_pool();
+        let mut classes = SizeClassAllocator::new();
+
+        let (ptr, _) = classes.alloc(40).unwrap();
+        classes.free(ptr, 40);
+
+        // Another size in the same class reuses the object
+        let (again, class) = classes.alloc(33).unwrap();
+        assert_eq!(class, 64);
+        assert_eq!(again, ptr);
+    }
+
+    #[test]
+    fn test_classes_are_naturally_aligned() {
+        let _pool = lock_pool();
+        let mut classes = SizeClassAllocator::new();
+
+        for (size, align) in [(96, 32), (128, 128), (192, 64), (2048, 2048)] {
+            for _ in 0..3 {
+                let (ptr, _) = classes.alloc(size).unwrap();
+                assert_eq!(ptr.as_ptr() as usize % align, 0);
+            }
+        }
+    }
+}
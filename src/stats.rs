@@ -0,0 +1,33 @@
+/// Snapshot of a `SlabAllocator`'s counters, returned by `stats`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SlabStats {
+    /// Objects handed out since the cache was created.
+    pub allocated: usize,
+    /// Objects given back since the cache was created.
+    pub freed: usize,
+    /// Allocations that failed, whatever the cause: the provider running out,
+    /// objects too large for any slab, or injected faults.
+    pub failed: usize,
+    /// Objects currently handed out.
+    pub live: usize,
+    /// Most objects ever handed out at once.
+    pub high_water: usize,
+    /// Free objects in the slabs the cache owns.
+    pub free: usize,
+    /// Pages the cache owns, including cached empty slabs.
+    pub pages: usize,
+    /// Bytes of those pages that can't hold objects: page headers, bitmaps,
+    /// alignment padding and the tail left after the last object.
+    pub wasted_bytes: usize,
+}
+
+/// Snapshot of a `PagePool`'s pages.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    /// Pages the pool manages.
+    pub total_pages: usize,
+    /// Pages currently handed out to caches.
+    pub used_pages: usize,
+    /// Pages ready to be handed out, given back or never used.
+    pub free_pages: usize,
+}
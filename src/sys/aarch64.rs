@@ -1,5 +1,16 @@
+use core::ptr::NonNull;
+
 pub mod syscalls {
     pub const EXIT: usize = 93;
+    pub const MUNMAP: usize = 215;
+    pub const MMAP: usize = 222;
+}
+
+pub mod mman {
+    pub const PROT_READ: usize = 0x1;
+    pub const PROT_WRITE: usize = 0x2;
+    pub const MAP_PRIVATE: usize = 0x02;
+    pub const MAP_ANONYMOUS: usize = 0x20;
 }
 
 /* __________ Syscalls __________ */
@@ -22,7 +33,144 @@ pub fn syscall_1(n: usize, a0: usize) -> isize {
     ret
 }
 
+#[inline(always)]
+pub fn syscall_2(n: usize, a0: usize, a1: usize) -> isize {
+    let ret: isize;
+
+    // SAFETY: We are issuing a raw syscall via inline assembly.
+    // The arguments are placed in the correct registers as required by the
+    // aarch64 convention, and we only use the return value from x0.
+    unsafe {
+        core::arch::asm!(
+            "svc 0",
+            in("x8") n,
+            in("x0") a0,
+            in("x1") a1,
+            lateout("x0") ret,
+            options(nostack)
+        );
+    }
+    ret
+}
+
+#[inline(always)]
+pub fn syscall_6(
+    n: usize,
+    a0: usize,
+    a1: usize,
+    a2: usize,
+    a3: usize,
+    a4: usize,
+    a5: usize,
+) -> isize {
+    let ret: isize;
+
+    // SAFETY: We are issuing a raw syscall via inline assembly.
+    // The arguments are placed in the correct registers as required by the
+    // aarch64 convention, and we only use the return value from x0.
+    unsafe {
+        core::arch::asm!(
+            "svc 0",
+            in("x8") n,
+            in("x0") a0,
+            in("x1") a1,
+            in("x2") a2,
+            in("x3") a3,
+            in("x4") a4,
+            in("x5") a5,
+            lateout("x0") ret,
+            options(nostack)
+        );
+    }
+    ret
+}
+
 /* __________ Helpers __________ */
 pub fn exit(code: usize) {
     syscall_1(syscalls::EXIT, code);
-}
\ No newline at end of file
+}
+
+/// Map `len` bytes of zeroed, private, read-write memory.
+/// Returns the kernel's negative errno on failure.
+pub fn mmap_anonymous(len: usize) -> Result<NonNull<u8>, isize> {
+    let ret = syscall_6(
+        syscalls::MMAP,
+        0,
+        len,
+        mman::PROT_READ | mman::PROT_WRITE,
+        mman::MAP_PRIVATE | mman::MAP_ANONYMOUS,
+        usize::MAX, // fd -1
+        0,
+    );
+    // Errors come back as -4095..=-1, anything else is the mapping
+    if (-4095..0).contains(&ret) {
+        return Err(ret);
+    }
+    NonNull::new(ret as *mut u8).ok_or(ret)
+}
+
+/// Unmap memory previously mapped with `mmap_anonymous`.
+///
+/// # Safety
+///
+/// Nothing may access `[addr, addr + len)` afterwards.
+pub unsafe fn munmap(addr: NonNull<u8>, len: usize) -> Result<(), isize> {
+    match syscall_2(syscalls::MUNMAP, addr.as_ptr() as usize, len) {
+        0 => Ok(()),
+        err => Err(err),
+    }
+}
+
+/* __________ Atomics __________ */
+/// Compare the two words at `dst` with `current` and, if both match, replace
+/// them with `new`, as one atomic operation built on an `ldaxp`/`stlxp`
+/// loop. Returns the words found, as `Ok` if the swap happened.
+///
+/// # Safety
+///
+/// `dst` must be valid for reads and writes and 16-byte aligned.
+#[inline(always)]
+pub unsafe fn compare_exchange_pair(
+    dst: *mut [usize; 2],
+    current: [usize; 2],
+    new: [usize; 2],
+) -> Result<[usize; 2], [usize; 2]> {
+    let (lo, hi): (usize, usize);
+    let swapped: usize;
+    // SAFETY: The caller guarantees dst is valid and aligned. On a mismatch
+    // the loaded pair is stored back, so the value returned is one that was
+    // really there rather than a torn read.
+    unsafe {
+        core::arch::asm!(
+            "2:",
+            "ldaxp {lo}, {hi}, [{dst}]",
+            "cmp {lo}, {cur_lo}",
+            "ccmp {hi}, {cur_hi}, #0, eq",
+            "b.ne 3f",
+            "stlxp {status:w}, {new_lo}, {new_hi}, [{dst}]",
+            "cbnz {status:w}, 2b",
+            "mov {swapped}, #1",
+            "b 4f",
+            "3:",
+            "stlxp {status:w}, {lo}, {hi}, [{dst}]",
+            "cbnz {status:w}, 2b",
+            "mov {swapped}, #0",
+            "4:",
+            dst = in(reg) dst,
+            cur_lo = in(reg) current[0],
+            cur_hi = in(reg) current[1],
+            new_lo = in(reg) new[0],
+            new_hi = in(reg) new[1],
+            lo = out(reg) lo,
+            hi = out(reg) hi,
+            status = out(reg) _,
+            swapped = out(reg) swapped,
+            options(nostack)
+        );
+    }
+    if swapped != 0 {
+        Ok([lo, hi])
+    } else {
+        Err([lo, hi])
+    }
+}
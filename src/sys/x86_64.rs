@@ -1,7 +1,18 @@
+use core::ptr::NonNull;
+
 pub mod syscalls {
+    pub const MMAP: usize = 9;
+    pub const MUNMAP: usize = 11;
     pub const EXIT: usize = 60;
 }
 
+pub mod mman {
+    pub const PROT_READ: usize = 0x1;
+    pub const PROT_WRITE: usize = 0x2;
+    pub const MAP_PRIVATE: usize = 0x02;
+    pub const MAP_ANONYMOUS: usize = 0x20;
+}
+
 /* __________ Syscalls __________ */
 #[inline(always)]
 pub fn syscall_1(n: usize, a0: usize) -> isize {
@@ -21,7 +32,136 @@ pub fn syscall_1(n: usize, a0: usize) -> isize {
     ret
 }
 
+#[inline(always)]
+pub fn syscall_2(n: usize, a0: usize, a1: usize) -> isize {
+    let ret: isize;
+    // SAFETY: We are issuing a raw syscall via inline assembly.
+    // The arguments are placed in the correct registers as required by the
+    // x86_64 syscall convention; the kernel clobbers rcx and r11.
+    unsafe {
+        core::arch::asm!(
+            "syscall",
+            in("rax") n,
+            in("rdi") a0,
+            in("rsi") a1,
+            lateout("rax") ret,
+            lateout("rcx") _,
+            lateout("r11") _,
+            options(nostack)
+        );
+    }
+    ret
+}
+
+#[inline(always)]
+pub fn syscall_6(
+    n: usize,
+    a0: usize,
+    a1: usize,
+    a2: usize,
+    a3: usize,
+    a4: usize,
+    a5: usize,
+) -> isize {
+    let ret: isize;
+    // SAFETY: We are issuing a raw syscall via inline assembly.
+    // The arguments are placed in the correct registers as required by the
+    // x86_64 syscall convention (r10 instead of rcx for the fourth one),
+    // and the kernel clobbers rcx and r11.
+    unsafe {
+        core::arch::asm!(
+            "syscall",
+            in("rax") n,
+            in("rdi") a0,
+            in("rsi") a1,
+            in("rdx") a2,
+            in("r10") a3,
+            in("r8") a4,
+            in("r9") a5,
+            lateout("rax") ret,
+            lateout("rcx") _,
+            lateout("r11") _,
+            options(nostack)
+        );
+    }
+    ret
+}
+
 /* __________ Helpers __________ */
 pub fn exit(code: usize) {
     syscall_1(syscalls::EXIT, code);
-}
\ No newline at end of file
+}
+
+/// Map `len` bytes of zeroed, private, read-write memory.
+/// Returns the kernel's negative errno on failure.
+pub fn mmap_anonymous(len: usize) -> Result<NonNull<u8>, isize> {
+    let ret = syscall_6(
+        syscalls::MMAP,
+        0,
+        len,
+        mman::PROT_READ | mman::PROT_WRITE,
+        mman::MAP_PRIVATE | mman::MAP_ANONYMOUS,
+        usize::MAX, // fd -1
+        0,
+    );
+    // Errors come back as -4095..=-1, anything else is the mapping
+    if (-4095..0).contains(&ret) {
+        return Err(ret);
+    }
+    NonNull::new(ret as *mut u8).ok_or(ret)
+}
+
+/// Unmap memory previously mapped with `mmap_anonymous`.
+///
+/// # Safety
+///
+/// Nothing may access `[addr, addr + len)` afterwards.
+pub unsafe fn munmap(addr: NonNull<u8>, len: usize) -> Result<(), isize> {
+    match syscall_2(syscalls::MUNMAP, addr.as_ptr() as usize, len) {
+        0 => Ok(()),
+        err => Err(err),
+    }
+}
+
+/* __________ Atomics __________ */
+/// Compare the two words at `dst` with `current` and, if both match, replace
+/// them with `new`, as one atomic `lock cmpxchg16b`. Returns the words found,
+/// as `Ok` if the swap happened.
+///
+/// # Safety
+///
+/// `dst` must be valid for reads and writes and 16-byte aligned, and the CPU
+/// must support `cmpxchg16b`, which every x86_64 chip but the very first does.
+#[inline(always)]
+pub unsafe fn compare_exchange_pair(
+    dst: *mut [usize; 2],
+    current: [usize; 2],
+    new: [usize; 2],
+) -> Result<[usize; 2], [usize; 2]> {
+    let (lo, hi): (usize, usize);
+    let swapped: u8;
+    // SAFETY: The caller guarantees dst is valid and aligned. rbx is reserved
+    // by LLVM, so the low half of `new` is swapped in and rbx restored after.
+    // LLVM may still hand rbx out for a `reg` operand, which the swap would
+    // clobber, so every operand sits in a fixed register.
+    unsafe {
+        core::arch::asm!(
+            "xchg rdi, rbx",
+            "lock cmpxchg16b xmmword ptr [rsi]",
+            "sete r8b",
+            "mov rbx, rdi",
+            in("rsi") dst,
+            inout("rdi") new[0] => _,
+            out("r8b") swapped,
+            in("rcx") new[1],
+            inout("rax") current[0] => lo,
+            inout("rdx") current[1] => hi,
+            options(nostack)
+        );
+    }
+    if swapped != 0 {
+        Ok([lo, hi])
+    } else {
+        Err([lo, hi])
+    }
+}
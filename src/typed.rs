@@ -0,0 +1,175 @@
+use core::alloc::Layout;
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+use core::ptr::{self, NonNull};
+
+use crate::SlabAllocator;
+use crate::pool::{PageProvider, StaticPool};
+
+/// Slab cache of `T` values, sized and aligned for `T`.
+pub struct TypedSlab<T, P: PageProvider = StaticPool> {
+    // Boxes only hold a shared reference, so freeing goes through the cell
+    slab: UnsafeCell<SlabAllocator<P>>,
+    _marker: PhantomData<T>,
+}
+
+impl<T> TypedSlab<T> {
+    pub const fn new() -> Self {
+        Self {
+            slab: UnsafeCell::new(SlabAllocator::with_layout(Layout::new::<T>())),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> Default for TypedSlab<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, P: PageProvider> TypedSlab<T, P> {
+    /// Create a cache taking its pages from `provider`.
+    pub fn with_provider(provider: P) -> Self {
+        Self {
+            slab: UnsafeCell::new(
+                SlabAllocator::with_layout(Layout::new::<T>()).with_provider(provider),
+            ),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Move `value` into a new object, or drop it and return `None` if the cache is out of memory.
+    pub fn alloc(&self, value: T) -> Option<SlabBox<'_, T, P>> {
+        // SAFETY: TypedSlab is !Sync and no other reference to the slab outlives its call.
+        let ptr = unsafe { (*self.slab.get()).alloc()? }.cast::<T>();
+        // SAFETY: The object is sized and aligned for T and not handed out to anyone else.
+        unsafe { ptr.as_ptr().write(value) };
+        Some(SlabBox { ptr, cache: self })
+    }
+
+    // SAFETY: ptr must come from alloc on this cache and hold no live value.
+    unsafe fn free(&self, ptr: NonNull<T>) {
+        unsafe { (*self.slab.get()).free(ptr.cast()) };
+    }
+}
+
+/// Owning pointer to a `T` in a `TypedSlab`, returned to its cache on drop.
+pub struct SlabBox<'a, T, P: PageProvider = StaticPool> {
+    ptr: NonNull<T>,
+    cache: &'a TypedSlab<T, P>,
+}
+
+impl<T, P: PageProvider> SlabBox<'_, T, P> {
+    /// Move the value out and give the memory back to the cache.
+    pub fn into_inner(boxed: Self) -> T {
+        let boxed = core::mem::ManuallyDrop::new(boxed);
+        // SAFETY: The value is initialized and, with Drop skipped, read exactly once.
+        unsafe {
+            let value = ptr::read(boxed.ptr.as_ptr());
+            boxed.cache.free(boxed.ptr);
+            value
+        }
+    }
+}
+
+impl<T, P: PageProvider> Deref for SlabBox<'_, T, P> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: The box owns an initialized T for its whole life.
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T, P: PageProvider> DerefMut for SlabBox<'_, T, P> {
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: The box owns an initialized T for its whole life.
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+impl<T, P: PageProvider> Drop for SlabBox<'_, T, P> {
+    fn drop(&mut self) {
+        // SAFETY: The value is initialized and dropped once, then its memory goes back.
+        unsafe {
+            ptr::drop_in_place(self.ptr.as_ptr());
+            self.cache.free(self.ptr);
+        }
+    }
+}
+
+impl<T: fmt::Debug, P: PageProvider> fmt::Debug for SlabBox<'_, T, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lock_pool;
+    use core::cell::Cell;
+
+    struct Tracked<'a> {
+        drops: &'a Cell<usize>,
+        value: u32,
+    }
+
+    impl Drop for Tracked<'_> {
+        fn drop(&mut self) {
+            self.drops.set(self.drops.get() + 1);
+        }
+    }
+
+    #[test]
+    fn test_box_drops_value_and_frees_memory() {
+        let _pool = lock_pool();
+        let drops = Cell::new(0);
+        let cache = TypedSlab::new();
+
+        let first = cache
+            .alloc(Tracked {
+                drops: &drops,
+                value: 7,
+            })
+            .unwrap();
+        assert_eq!(first.value, 7);
+        let addr = &*first as *const Tracked as usize;
+        drop(first);
+        assert_eq!(drops.get(), 1);
+
+        // The object went back to the cache and is reused
+        let mut second = cache
+            .alloc(Tracked {
+                drops: &drops,
+                value: 8,
+            })
+            .unwrap();
+        second.value += 1;
+        assert_eq!(&*second as *const Tracked as usize, addr);
+
+        // into_inner hands the value over without dropping it
+        let inner = SlabBox::into_inner(second);
+        assert_eq!(inner.value, 9);
+        assert_eq!(drops.get(), 1);
+    }
+
+    #[test]
+    fn test_boxes_are_aligned_for_t() {
+        let _pool = lock_pool();
+
+        #[repr(align(64))]
+        struct CacheLine([u8; 40]);
+
+        let cache = TypedSlab::new();
+        let boxes: [_; 4] =
+            core::array::from_fn(|i| cache.alloc(CacheLine([i as u8; 40])).unwrap());
+        for (i, boxed) in boxes.iter().enumerate() {
+            assert_eq!(&**boxed as *const CacheLine as usize % 64, 0);
+            assert_eq!(boxed.0[0], i as u8);
+        }
+    }
+}